@@ -6,13 +6,16 @@
 CREATE TABLE guilds (
     uuid INT PRIMARY KEY,
     name TEXT UNIQUE NOT NULL,
+    tag TEXT UNIQUE NOT NULL, -- Short tag shown in chat
     experience_points NUMERIC DEFAULT 0 -- Guild experience points
 );
 
 -- Ranks Table (Dynamic Rank System)
 CREATE TABLE ranks (
     id SERIAL PRIMARY KEY,
-    name TEXT UNIQUE NOT NULL
+    name TEXT UNIQUE NOT NULL,
+    prefix TEXT NOT NULL DEFAULT '', -- Shown before the name, may contain formatting codes
+    color CHAR(1) NOT NULL DEFAULT '7' -- Formatting code used for the name
 );
 
 -- Rank Permissions Table
@@ -57,6 +60,16 @@ CREATE TABLE minigame_stats (
     UNIQUE (player_id, minigame, stat_key)
 );
 
+-- Ranked Ratings Table (one row per player, minigame and season)
+CREATE TABLE ratings (
+    player_id NUMERIC(39,0) REFERENCES players(uuid) ON DELETE CASCADE,
+    minigame TEXT NOT NULL,
+    season INT NOT NULL,
+    rating DOUBLE PRECISION NOT NULL,
+    games INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (player_id, minigame, season)
+);
+
 -- Minigame Inventories Table
 CREATE TABLE minigame_inventories (
     player_id NUMERIC(39,0) REFERENCES players(uuid) ON DELETE CASCADE,
@@ -52,6 +52,12 @@ struct ServerConfig {
     network: NetworkConfig,
 }
 
+#[derive(Default, Deserialize, Serialize, Clone)]
+#[serde(default)]
+struct DatabaseConfig {
+    url: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(default)]
 struct ForwardingConfig {
@@ -76,6 +82,7 @@ struct Config {
     data_path: PathBuf,
 
     #[clap(skip)] forwarding: ForwardingConfig,
+    #[clap(skip)] database: DatabaseConfig,
 
     #[clap(skip)] lobby: ServerConfig,
     #[clap(skip)] bedwars: ServerConfig,
@@ -143,6 +150,8 @@ fn main() {
         cloned_config.path = config.data_path.join(cloned_config.path);
         cloned_config.network.forwarding_secret = config.forwarding.secret.clone();
         cloned_config.network.connection_mode = config.forwarding.mode;
+        cloned_config.network.server_name = server.to_string();
+        cloned_config.network.database_url = config.database.url.clone();
         println!("{}", cloned_config.network.forwarding_secret);
 
         println!("Starting server {}", server);
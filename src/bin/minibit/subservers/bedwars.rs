@@ -2,12 +2,11 @@
 
 use std::collections::HashSet;
 use std::marker::PhantomData;
-use bevy_ecs::query::QueryData;
-use minibit_lib::color::ArmorColors;
+use minibit_lib::combat::{CombatPlugin, CombatQuery, DamageMode, KnockbackProfile, damage_player};
 use minibit_lib::config::WorldValue;
-use minibit_lib::damage::calc_dmg;
-use minibit_lib::damage::calc_dmg_with_weapon;
+use minibit_lib::damage::{DamageType, calc_dmg};
 use minibit_lib::duels::*;
+use minibit_lib::kits::Kits;
 use minibit_lib::player::*;
 use minibit_lib::projectiles::*;
 use minibit_lib::world::*;
@@ -17,18 +16,11 @@ use valence::entity::item::Stack;
 use valence::entity::living::Absorption;
 use valence::entity::living::Health;
 use valence::entity::Velocity;
-use valence::entity::{EntityId, EntityStatuses};
 use valence::equipment::EquipmentInventorySync;
-use valence::inventory::HeldItem;
 use valence::math::IVec3;
-use valence::math::Vec3Swizzles;
-use valence::nbt::compound;
 use valence::prelude::*;
-use valence::protocol::packets::play::DamageTiltS2c;
 use valence::protocol::sound::SoundCategory;
 use valence::protocol::Sound;
-use valence::protocol::VarInt;
-use valence::protocol::WritePacket;
 use minibit_lib::death::{DeathEvent, DeathPlugin, DeathSet};
 use minibit_lib::duels::oob::{OobMode, OobPlugin};
 use minibit_lib::food::golden_apple::GoldenApplePlugin;
@@ -64,10 +56,16 @@ pub fn main(config: ServerConfig) {
             path: config.path,
             network_config: config.network,
             default_gamemode: GameMode::Survival,
+            team_size: 1,
+            team_count: 2,
             copy_map: true,
             phantom: PhantomData,
         })
         .add_plugins(DefaultPlugins)
+        .add_plugins(CombatPlugin {
+            damage: DamageMode::Armor,
+            deaths_end_game: false,
+        })
         .add_plugins((
             InteractionBroadcastPlugin,
             DisableDropPlugin,
@@ -97,11 +95,11 @@ pub fn main(config: ServerConfig) {
         ))
         .add_event::<MessageEvent>()
         .add_systems(Startup, setup)
-        .add_systems(EventLoopUpdate, handle_combat_events)
         .add_systems(
             Update,
             (
                 init_clients,
+                handle_reconnect.after(init_clients).after(reconnect::reconnect),
                 start_game,
                 end_game,
                 gen_iron,
@@ -129,52 +127,32 @@ fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
     }
 }
 
+fn handle_reconnect(mut players: Query<&mut BedwarsState>, mut reconnects: EventReader<reconnect::ReconnectEvent>) {
+    for event in reconnects.read() {
+        if let Ok([old, mut new]) = players.get_many_mut([event.old, event.new]) {
+            new.bed_broken = old.bed_broken;
+        }
+    }
+}
+
 fn start_game(
     mut clients: Query<(&mut GameMode, &mut Inventory, &PlayerGameState), With<Client>>,
-    mut games: Query<(&Entities, &mut GameData)>,
+    games: Query<&Entities>,
     mut start_game: EventReader<StartGameEvent>,
+    kits: Res<Kits>,
 ) {
     for event in start_game.read() {
-        if let Ok((entities, mut data)) = games.get_mut(event.0) {
-            data.0.insert(0, DataValue::Int(0));
-            data.0.insert(1, DataValue::Int(0));
-
+        if let Ok(entities) = games.get(event.0) {
             for entity in entities.0.iter() {
                 if let Ok((mut gamemode, mut inventory, gamestate)) = clients.get_mut(*entity) {
                     *gamemode = GameMode::Survival;
-                    fill_inventory(&mut inventory, gamestate.team);
+                    kits.give("default", &mut inventory, gamestate.team);
                 }
             }
         }
     }
 }
 
-fn fill_inventory(inv: &mut Inventory, team: u8) {
-    let armor_nbt = Some(compound! {
-        "display" => compound! {
-            "color" => match team {
-                0 => ArmorColors::Blue as i32,
-                1 => ArmorColors::Red as i32,
-                _ => 0,
-            }
-        }
-    });
-    inv.set_slot(
-        5,
-        ItemStack::new(ItemKind::LeatherHelmet, 1, armor_nbt.clone()),
-    );
-    inv.set_slot(
-        6,
-        ItemStack::new(ItemKind::LeatherChestplate, 1, armor_nbt.clone()),
-    );
-    inv.set_slot(
-        7,
-        ItemStack::new(ItemKind::LeatherLeggings, 1, armor_nbt.clone()),
-    );
-    inv.set_slot(8, ItemStack::new(ItemKind::LeatherBoots, 1, armor_nbt));
-    inv.set_slot(36, ItemStack::new(ItemKind::WoodenSword, 1, None));
-}
-
 fn end_game(
     mut clients: Query<(&mut GameMode, &mut Inventory), With<Client>>,
     games: Query<&Entities>,
@@ -196,7 +174,7 @@ fn end_game(
 
 fn gen_iron(
     mut commands: Commands,
-    games: Query<&EntityLayerId, With<GameData>>,
+    games: Query<&EntityLayerId, With<Entities>>,
     config: Res<BedwarsConfig>,
     server: Res<Server>,
 ) {
@@ -215,124 +193,39 @@ fn gen_iron(
     }
 }
 
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct CombatQuery {
-    entity: Entity,
-    client: &'static mut Client,
-    id: &'static EntityId,
-    pos: &'static Position,
-    old_pos: &'static OldPosition,
-    state: &'static mut CombatState,
-    statuses: &'static mut EntityStatuses,
-    gamestate: &'static PlayerGameState,
-    health: &'static mut Health,
-    absorption: &'static mut Absorption,
-    held_item: &'static HeldItem,
-    inv: &'static Inventory,
-}
-
-fn handle_combat_events(
-    server: Res<Server>,
-    mut clients: Query<CombatQuery>,
-    mut sprinting: EventReader<SprintEvent>,
-    mut interact_entity: EventReader<InteractEntityEvent>,
-    mut deaths: EventWriter<DeathEvent>,
-) {
-    for &SprintEvent { client, state } in sprinting.read() {
-        if let Ok(mut client) = clients.get_mut(client) {
-            client.state.has_bonus_knockback = state == SprintState::Start;
-        }
-    }
-
-    for &InteractEntityEvent {
-        client: attacker_client,
-        entity: victim_client,
-        interact: interaction,
-        ..
-    } in interact_entity.read()
-    {
-        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
-        else {
-            continue;
-        };
-
-        if interaction != EntityInteraction::Attack
-            || server.current_tick() - victim.state.last_attacked_tick < 10
-            || attacker.gamestate.team == victim.gamestate.team
-            || attacker.gamestate.game_id != victim.gamestate.game_id
-        {
-            continue;
-        }
-
-        victim.state.last_attacked_tick = server.current_tick();
-
-        let victim_pos = victim.pos.0.xz();
-        let attacker_pos = attacker.pos.0.xz();
-
-        let dir = (victim_pos - attacker_pos).normalize().as_vec2();
-
-        let knockback_xz = if attacker.state.has_bonus_knockback {
-            18.0
-        } else {
-            8.0
-        };
-        let knockback_y = if attacker.state.has_bonus_knockback {
-            8.432
-        } else {
-            6.432
-        };
-
-        let dmg = calc_dmg_with_weapon(
-            attacker.inv.slot(attacker.held_item.slot()).item,
-            victim.inv.slot(5).item,
-            victim.inv.slot(6).item,
-            victim.inv.slot(7).item,
-            victim.inv.slot(8).item
-        );
-
-        damage_player(
-            &mut attacker,
-            &mut victim,
-            dmg,
-            Vec3::new(dir.x * knockback_xz, knockback_y, dir.y * knockback_xz),
-            &mut deaths,
-        );
-
-        attacker.state.has_bonus_knockback = false;
-    }
-}
-
 fn handle_collision_events(
     mut clients: Query<CombatQuery>,
-    arrows: Query<(&Velocity, &ProjectileOwner)>,
+    arrows: Query<(&Velocity, &ProjectileOwner, Has<CriticalArrow>, &ArrowEnchantments)>,
     mut collisions: EventReader<ProjectileCollisionEvent>,
     mut deaths: EventWriter<DeathEvent>,
+    knockback: Res<KnockbackProfile>,
 ) {
     for event in collisions.read() {
-        if let Ok((vel, owner)) = arrows.get(event.arrow)
+        if let Ok((vel, owner, critical, enchantments)) = arrows.get(event.projectile)
             && let Ok([mut attacker, mut victim]) = clients.get_many_mut([owner.0, event.player])
         {
             if attacker.gamestate.team == victim.gamestate.team {
                 continue;
             }
 
-            // TODO: Make the damage accurate
             let dmg = calc_dmg(
-                0.13 * vel.0.length(),
-                victim.inv.slot(5).item,
-                victim.inv.slot(6).item,
-                victim.inv.slot(7).item,
-                victim.inv.slot(8).item
+                arrow_damage(vel.0, critical, enchantments),
+                victim.inv.slot(5),
+                victim.inv.slot(6),
+                victim.inv.slot(7),
+                victim.inv.slot(8),
+                DamageType::Projectile,
             );
 
-            damage_player(
+            if damage_player(
                 &mut attacker,
                 &mut victim,
                 dmg,
-                vel.0.normalize().with_y(0.0) * 0.6 * 20.0, // TODO: Make the knockback accurate
-                &mut deaths,
-            );
+                arrow_knockback(vel.0, knockback.horizontal, knockback.vertical, enchantments),
+                &knockback,
+            ) {
+                deaths.send(DeathEvent(victim.entity, true));
+            }
             attacker.client.play_sound(
                 Sound::EntityArrowHitPlayer,
                 SoundCategory::Player,
@@ -366,6 +259,8 @@ fn handle_death(
     mut deaths: EventReader<DeathEvent>,
     mut broadcasts: EventWriter<MessageEvent>,
     config: Res<BedwarsConfig>,
+    settings: Res<GameSettings>,
+    kits: Res<Kits>,
 ) {
     for DeathEvent(entity, show) in deaths.read() {
         if let Ok((
@@ -388,7 +283,7 @@ fn handle_death(
                 *gamemode = GameMode::Spectator;
                 pos.0 += DVec3::new(0.0, 10.0, 0.0);
             } else {
-                let spawn = &config.worlds[map_index.0].spawns[gamestate.team as usize];
+                let spawn = config.worlds[map_index.0].spawn(gamestate.team, gamestate.slot, settings.team_size);
                 pos.0 = spawn.pos.into();
                 look.yaw = spawn.rot[0];
                 look.pitch = spawn.rot[1];
@@ -399,7 +294,7 @@ fn handle_death(
             for slot in 0..inventory.slot_count() {
                 inventory.set_slot(slot, ItemStack::EMPTY);
             }
-            fill_inventory(&mut inventory, gamestate.team);
+            kits.give("default", &mut inventory, gamestate.team);
             if *show {
                 broadcasts.send(MessageEvent {
                     game: game_id,
@@ -448,12 +343,12 @@ fn check_for_winners(
         if teams_alive.len() == 1 {
             end_game.send(EndGameEvent {
                 game_id,
-                loser: if teams_alive.contains(&0) { 1 } else { 0 },
+                result: GameResult::Ranking(teams_alive.into_iter().collect()),
             });
         } else if teams_alive.is_empty() {
             end_game.send(EndGameEvent {
                 game_id,
-                loser: 2,
+                result: GameResult::Draw,
             });
         }
     }
@@ -520,59 +415,3 @@ fn game_broadcast(
         }
     }
 }
-
-// Helper functions below
-
-fn damage_player(
-    attacker: &mut CombatQueryItem,
-    victim: &mut CombatQueryItem,
-    damage: f32,
-    velocity: Vec3,
-    deaths: &mut EventWriter<DeathEvent>,
-) {
-    let old_vel = Vec3::new(
-        (victim.pos.0.x - victim.old_pos.get().x) as f32,
-        (victim.pos.0.y - victim.old_pos.get().y) as f32,
-        (victim.pos.0.z - victim.old_pos.get().z) as f32,
-    );
-
-    victim.client.set_velocity(old_vel + velocity);
-
-    attacker.state.has_bonus_knockback = false;
-
-    victim.client.play_sound(
-        Sound::EntityPlayerHurt,
-        SoundCategory::Player,
-        victim.pos.0,
-        1.0,
-        1.0,
-    );
-    victim.client.write_packet(&DamageTiltS2c {
-        entity_id: VarInt(0),
-        yaw: 0.0,
-    });
-    attacker.client.play_sound(
-        Sound::EntityPlayerHurt,
-        SoundCategory::Player,
-        victim.pos.0,
-        1.0,
-        1.0,
-    );
-    attacker.client.write_packet(&DamageTiltS2c {
-        entity_id: VarInt(victim.id.get()),
-        yaw: 0.0,
-    });
-
-    victim.state.last_attacker = Some(attacker.entity);
-
-    let mut new_damage = damage;
-    if victim.absorption.0 > 0.0 {
-        new_damage -= damage.min(victim.absorption.0);
-        victim.absorption.0 -= damage.min(victim.absorption.0);
-    }
-    if victim.health.0 <= new_damage {
-        deaths.send(DeathEvent(victim.entity, true));
-    } else {
-        victim.health.0 -= new_damage;
-    }
-}
\ No newline at end of file
@@ -1,16 +1,9 @@
-#![allow(clippy::type_complexity)]
-
 use std::marker::PhantomData;
-use bevy_ecs::query::QueryData;
-use minibit_lib::duels::{CombatState, DefaultDuelsConfig, DuelsPlugin, EndGameEvent, Entities, PlayerGameState};
-use valence::entity::{EntityId, EntityStatuses};
-use valence::math::Vec3Swizzles;
+use minibit_lib::combat::{CombatPlugin, DamageMode, HitEvent};
+use minibit_lib::duels::{DefaultDuelsConfig, DuelsPlugin, EndGameEvent, Entities, GameResult, PlayerGameState};
+use minibit_lib::duels::reconnect::{ReconnectEvent, reconnect};
+use minibit_lib::duels::time_limit::TeamScores;
 use valence::prelude::*;
-use valence::protocol::packets::play::DamageTiltS2c;
-use valence::protocol::sound::SoundCategory;
-use valence::protocol::Sound;
-use valence::protocol::VarInt;
-use valence::protocol::WritePacket;
 use crate::ServerConfig;
 
 #[derive(Component, Default)]
@@ -24,15 +17,22 @@ pub fn main(config: ServerConfig) {
             path: config.path,
             network_config: config.network,
             default_gamemode: GameMode::Adventure,
+            team_size: 1,
+            team_count: 2,
             copy_map: false,
             phantom: PhantomData
         })
         .add_plugins(DefaultPlugins)
-        .add_systems(EventLoopUpdate, handle_combat_events)
+        .add_plugins(CombatPlugin {
+            damage: DamageMode::None,
+            deaths_end_game: false,
+        })
         .add_systems(
             Update,
             (
+                handle_hits,
                 init_clients.after(minibit_lib::duels::map::init_clients::<DefaultDuelsConfig>),
+                handle_reconnect.after(init_clients).after(reconnect),
                 end_game.after(minibit_lib::duels::map::end_game::<DefaultDuelsConfig>),
             ),
         )
@@ -45,109 +45,41 @@ fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
     }
 }
 
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct CombatQuery {
-    client: &'static mut Client,
-    id: &'static EntityId,
-    pos: &'static Position,
-    state: &'static mut CombatState,
-    statuses: &'static mut EntityStatuses,
-    gamestate: &'static PlayerGameState,
-    boxing_state: &'static mut BoxingState,
+fn handle_reconnect(mut clients: Query<&mut BoxingState>, mut reconnects: EventReader<ReconnectEvent>) {
+    for event in reconnects.read() {
+        if let Ok([old, mut new]) = clients.get_many_mut([event.old, event.new]) {
+            new.hits = old.hits;
+        }
+    }
 }
 
-fn handle_combat_events(
-    server: Res<Server>,
-    mut clients: Query<CombatQuery>,
-    mut sprinting: EventReader<SprintEvent>,
-    mut interact_entity: EventReader<InteractEntityEvent>,
+fn handle_hits(
+    mut clients: Query<(&mut Client, &PlayerGameState, &mut BoxingState)>,
+    mut games: Query<&mut TeamScores>,
+    mut hits: EventReader<HitEvent>,
     mut end_game: EventWriter<EndGameEvent>,
 ) {
-    for &SprintEvent { client, state } in sprinting.read() {
-        if let Ok(mut client) = clients.get_mut(client) {
-            client.state.has_bonus_knockback = state == SprintState::Start;
-        }
-    }
-
-    for &InteractEntityEvent {
-        client: attacker_client,
-        entity: victim_client,
-        interact: interaction,
-        ..
-    } in interact_entity.read()
-    {
-        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
+    for event in hits.read() {
+        let Ok([(mut attacker, attacker_state, _), (mut victim, victim_state, mut boxing_state)]) =
+            clients.get_many_mut([event.attacker, event.victim])
         else {
             continue;
         };
-
-        if interaction != EntityInteraction::Attack
-            || server.current_tick() - victim.state.last_attacked_tick < 10
-            || attacker.gamestate.game_id != victim.gamestate.game_id
-        {
+        let Some(game_id) = attacker_state.game_id else {
             continue;
-        }
-
-        victim.state.last_attacked_tick = server.current_tick();
-
-        let victim_pos = victim.pos.0.xz();
-        let attacker_pos = attacker.pos.0.xz();
-
-        let dir = (victim_pos - attacker_pos).normalize().as_vec2();
-
-        let knockback_xz = if attacker.state.has_bonus_knockback {
-            18.0
-        } else {
-            8.0
-        };
-        let knockback_y = if attacker.state.has_bonus_knockback {
-            8.432
-        } else {
-            6.432
         };
 
-        victim
-            .client
-            .set_velocity([dir.x * knockback_xz, knockback_y, dir.y * knockback_xz]);
-
-        attacker.state.has_bonus_knockback = false;
-
-        victim.client.play_sound(
-            Sound::EntityPlayerHurt,
-            SoundCategory::Player,
-            victim.pos.0,
-            1.0,
-            1.0,
-        );
-        victim.client.write_packet(&DamageTiltS2c {
-            entity_id: VarInt(0),
-            yaw: 0.0,
-        });
-        attacker.client.play_sound(
-            Sound::EntityPlayerHurt,
-            SoundCategory::Player,
-            victim.pos.0,
-            1.0,
-            1.0,
-        );
-        attacker.client.write_packet(&DamageTiltS2c {
-            entity_id: VarInt(victim.id.get()),
-            yaw: 0.0,
-        });
-
-        victim.boxing_state.hits += 1;
+        boxing_state.hits += 1;
+        if let Ok(mut scores) = games.get_mut(game_id) {
+            scores.add(attacker_state.team, 1);
+        }
 
-        if victim.boxing_state.hits >= 5 {
-            victim
-                .client
-                .send_chat_message("You have been knocked out!");
-            attacker
-                .client
-                .send_chat_message("You have knocked out your opponent!");
+        if boxing_state.hits >= 5 {
+            victim.send_chat_message("You have been knocked out!");
+            attacker.send_chat_message("You have knocked out your opponent!");
             end_game.send(EndGameEvent {
-                game_id: victim.gamestate.game_id.unwrap(),
-                loser: victim.gamestate.team,
+                game_id,
+                result: GameResult::Loser(victim_state.team),
             });
         }
     }
@@ -1,18 +1,20 @@
 #![allow(clippy::type_complexity)]
 
 use std::marker::PhantomData;
-use std::time::SystemTime;
 
 use crate::ServerConfig;
-use bevy_ecs::query::QueryData;
-use minibit_lib::color::{format, ArmorColors};
+use minibit_lib::color::format;
+use minibit_lib::combat::{damage_player, CombatPlugin, CombatQuery, DamageMode, KnockbackProfile};
 use minibit_lib::config::WorldValue;
-use minibit_lib::damage::calc_dmg;
-use minibit_lib::damage::calc_dmg_with_weapon;
+use minibit_lib::damage::{calc_dmg, DamageType};
 use minibit_lib::death::{DeathEvent, DeathPlugin, DeathSet};
 use minibit_lib::duels::oob::{OobMode, OobPlugin};
+use minibit_lib::duels::reconnect::{ReconnectEvent, reconnect};
+use minibit_lib::duels::stages::NewRoundEvent;
+use minibit_lib::duels::time_limit::TeamScores;
 use minibit_lib::duels::*;
 use minibit_lib::food::golden_apple::GoldenApplePlugin;
+use minibit_lib::kits::Kits;
 use minibit_lib::player::*;
 use minibit_lib::projectiles::*;
 use minibit_lib::scoreboard::{gen_scores, ScoreboardId, ScoreboardMode, ScoreboardPlugin};
@@ -21,15 +23,9 @@ use serde::Deserialize;
 use valence::entity::living::Absorption;
 use valence::entity::living::Health;
 use valence::entity::Velocity;
-use valence::entity::{EntityId, EntityStatuses};
 use valence::equipment::EquipmentInventorySync;
-use valence::inventory::HeldItem;
 use valence::math::IVec3;
-use valence::math::Vec3Swizzles;
-use valence::nbt::compound;
-use valence::nbt::List;
 use valence::prelude::*;
-use valence::protocol::packets::play::DamageTiltS2c;
 use valence::protocol::packets::play::ExperienceBarUpdateS2c;
 use valence::protocol::sound::SoundCategory;
 use valence::protocol::Sound;
@@ -87,10 +83,16 @@ pub fn main(config: ServerConfig) {
             path: config.path,
             network_config: config.network,
             default_gamemode: GameMode::Survival,
+            team_size: 1,
+            team_count: 2,
             copy_map: true,
             phantom: PhantomData,
         })
         .add_plugins(DefaultPlugins)
+        .add_plugins(CombatPlugin {
+            damage: DamageMode::Armor,
+            deaths_end_game: false,
+        })
         .add_plugins((
             ScoreboardPlugin {
                 name: "BRIDGE",
@@ -125,16 +127,17 @@ pub fn main(config: ServerConfig) {
         .add_event::<ScoreEvent>()
         .add_event::<MessageEvent>()
         .add_systems(Startup, setup)
-        .add_systems(EventLoopUpdate, handle_combat_events)
         .add_systems(
             Update,
             (
                 init_clients,
+                handle_reconnect.after(init_clients).after(reconnect),
                 start_game,
                 gamestage_change,
                 end_game,
                 check_goals,
-                update_bow_cooldown,
+                start_bow_cooldown,
+                update_bow_cooldown.after(start_bow_cooldown),
                 handle_collision_events,
                 handle_death.after(DeathSet),
                 handle_score.after(check_goals).before(handle_death),
@@ -159,19 +162,31 @@ fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
     }
 }
 
+fn handle_reconnect(
+    mut clients: Query<(&mut BowStatus, &mut PlayerStatistics)>,
+    mut reconnects: EventReader<ReconnectEvent>,
+) {
+    for event in reconnects.read() {
+        if let Ok([(old_bow, old_stats), (mut new_bow, mut new_stats)]) = clients.get_many_mut([event.old, event.new]) {
+            new_bow.cooldown = old_bow.cooldown;
+            new_bow.slot = old_bow.slot;
+            new_stats.kills = old_stats.kills;
+            new_stats.deaths = old_stats.deaths;
+        }
+    }
+}
+
 fn start_game(
     mut clients: Query<(&mut Inventory, &PlayerGameState, &mut PlayerStatistics), With<Client>>,
-    mut games: Query<(&Entities, &mut GameData)>,
+    games: Query<&Entities>,
     mut start_game: EventReader<StartGameEvent>,
+    kits: Res<Kits>,
 ) {
     for event in start_game.read() {
-        if let Ok((entities, mut data)) = games.get_mut(event.0) {
-            data.0.insert(0, DataValue::Int(0));
-            data.0.insert(1, DataValue::Int(0));
-
+        if let Ok(entities) = games.get(event.0) {
             for entity in entities.0.iter() {
                 if let Ok((mut inventory, gamestate, mut stats)) = clients.get_mut(*entity) {
-                    fill_inventory(&mut inventory, gamestate.team);
+                    kits.give("default", &mut inventory, gamestate.team);
                     stats.kills = 0;
                     stats.deaths = 0;
                 }
@@ -196,9 +211,8 @@ fn gamestage_change(
 
         match event.stage {
             0 | 2 => { // For some reason the blocks are overwritten at the start of the game, so we need to reapply them
-                for i in 0..2 {
-                    let spawn_pos =
-                        DVec3::from_array(server_config.worlds()[map_idx.0].spawns[i].pos);
+                for spawn in server_config.worlds()[map_idx.0].spawns.iter() {
+                    let spawn_pos = DVec3::from_array(spawn.pos);
                     // Create the cage
                     for x in -2..=2 {
                         for y in -1..=3 {
@@ -216,9 +230,8 @@ fn gamestage_change(
             }
             4 => {
                 // Clear the cages
-                for i in 0..2 {
-                    let spawn_pos =
-                        DVec3::from_array(server_config.worlds()[map_idx.0].spawns[i].pos);
+                for spawn in server_config.worlds()[map_idx.0].spawns.iter() {
+                    let spawn_pos = DVec3::from_array(spawn.pos);
                     for x in -2..=2 {
                         for y in -1..=3 {
                             for z in -2..=2 {
@@ -236,46 +249,6 @@ fn gamestage_change(
     }
 }
 
-fn fill_inventory(inv: &mut Inventory, team: u8) {
-    let armor_nbt = Some(compound! {
-        "display" => compound! {
-            "color" => match team {
-                0 => ArmorColors::Blue as i32,
-                1 => ArmorColors::Red as i32,
-                _ => 0,
-            }
-        }
-    });
-    let block_type = match team {
-        0 => ItemKind::BlueTerracotta,
-        1 => ItemKind::RedTerracotta,
-        _ => ItemKind::WhiteTerracotta,
-    };
-    inv.set_slot(
-        6,
-        ItemStack::new(ItemKind::LeatherChestplate, 1, armor_nbt.clone()),
-    );
-    inv.set_slot(
-        7,
-        ItemStack::new(ItemKind::LeatherLeggings, 1, armor_nbt.clone()),
-    );
-    inv.set_slot(8, ItemStack::new(ItemKind::LeatherBoots, 1, armor_nbt));
-    inv.set_slot(36, ItemStack::new(ItemKind::IronSword, 1, None));
-    inv.set_slot(37, ItemStack::new(ItemKind::Bow, 1, None));
-    inv.set_slot(38, ItemStack::new(ItemKind::DiamondPickaxe, 1, Some(compound! {
-        "Enchantments" => List::Compound(vec! [
-            compound! {
-                "id" => "efficiency",
-                "lvl" => 2
-            }
-        ])
-    })));
-    inv.set_slot(39, ItemStack::new(block_type, 64, None));
-    inv.set_slot(40, ItemStack::new(block_type, 64, None));
-    inv.set_slot(41, ItemStack::new(ItemKind::GoldenApple, 8, None));
-    inv.set_slot(44, ItemStack::new(ItemKind::Arrow, 1, None));
-}
-
 fn end_game(
     mut clients: Query<&mut Inventory, With<Client>>,
     games: Query<&Entities>,
@@ -318,17 +291,28 @@ fn check_goals(
     }
 }
 
+fn start_bow_cooldown(
+    mut clients: Query<&mut BowStatus>,
+    mut shots: EventReader<BowShootEvent>,
+    server: Res<Server>,
+) {
+    for event in shots.read() {
+        if let Ok(mut bow_status) = clients.get_mut(event.shooter)
+            && let Some(slot) = event.arrow_slot
+        {
+            bow_status.cooldown = server.current_tick() + 60;
+            bow_status.slot = slot;
+        }
+    }
+}
+
 fn update_bow_cooldown(
     mut clients: Query<(&mut Client, &mut Inventory, &CursorItem, &mut BowStatus)>,
     server: Res<Server>,
 ) {
     for (mut client, mut inv, cursor_item, mut bow_status) in clients.iter_mut() {
         let tick = server.current_tick();
-        if let Some(slot) = inv.first_slot_with_item(ItemKind::Arrow, 2)
-            && (inv.slot(slot).count > 0 || cursor_item.0.item == ItemKind::Arrow)
-        {
-            bow_status.cooldown = tick + 60;
-            bow_status.slot = slot;
+        if bow_status.cooldown == i64::MAX {
             continue;
         }
 
@@ -347,7 +331,13 @@ fn update_bow_cooldown(
             });
         }
         if bow_status.cooldown < tick {
-            inv.set_slot(bow_status.slot, ItemStack::new(ItemKind::Arrow, 1, None));
+            // Respawning hands out a new arrow, so don't add a second one
+            if inv.first_slot_with_item(ItemKind::Arrow, 65).is_none()
+                && cursor_item.0.item != ItemKind::Arrow
+            {
+                inv.set_slot(bow_status.slot, ItemStack::new(ItemKind::Arrow, 1, None));
+            }
+            bow_status.cooldown = i64::MAX;
             client.write_packet(&ExperienceBarUpdateS2c {
                 bar: 0.0,
                 level: VarInt(0),
@@ -357,124 +347,39 @@ fn update_bow_cooldown(
     }
 }
 
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct CombatQuery {
-    entity: Entity,
-    client: &'static mut Client,
-    id: &'static EntityId,
-    pos: &'static Position,
-    old_pos: &'static OldPosition,
-    state: &'static mut CombatState,
-    statuses: &'static mut EntityStatuses,
-    gamestate: &'static PlayerGameState,
-    health: &'static mut Health,
-    absorption: &'static mut Absorption,
-    held_item: &'static HeldItem,
-    inv: &'static Inventory,
-}
-
-fn handle_combat_events(
-    server: Res<Server>,
-    mut clients: Query<CombatQuery>,
-    mut sprinting: EventReader<SprintEvent>,
-    mut interact_entity: EventReader<InteractEntityEvent>,
-    mut deaths: EventWriter<DeathEvent>,
-) {
-    for &SprintEvent { client, state } in sprinting.read() {
-        if let Ok(mut client) = clients.get_mut(client) {
-            client.state.has_bonus_knockback = state == SprintState::Start;
-        }
-    }
-
-    for &InteractEntityEvent {
-        client: attacker_client,
-        entity: victim_client,
-        interact: interaction,
-        ..
-    } in interact_entity.read()
-    {
-        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
-        else {
-            continue;
-        };
-
-        if interaction != EntityInteraction::Attack
-            || server.current_tick() - victim.state.last_attacked_tick < 10
-            || attacker.gamestate.team == victim.gamestate.team
-            || attacker.gamestate.game_id != victim.gamestate.game_id
-        {
-            continue;
-        }
-
-        victim.state.last_attacked_tick = server.current_tick();
-
-        let victim_pos = victim.pos.0.xz();
-        let attacker_pos = attacker.pos.0.xz();
-
-        let dir = (victim_pos - attacker_pos).normalize().as_vec2();
-
-        let knockback_xz = if attacker.state.has_bonus_knockback {
-            18.0
-        } else {
-            8.0
-        };
-        let knockback_y = if attacker.state.has_bonus_knockback {
-            8.432
-        } else {
-            6.432
-        };
-
-        let dmg = calc_dmg_with_weapon(
-            attacker.inv.slot(attacker.held_item.slot()).item,
-            victim.inv.slot(5).item,
-            victim.inv.slot(6).item,
-            victim.inv.slot(7).item,
-            victim.inv.slot(8).item
-        );
-
-        damage_player(
-            &mut attacker,
-            &mut victim,
-            dmg,
-            Vec3::new(dir.x * knockback_xz, knockback_y, dir.y * knockback_xz),
-            &mut deaths,
-        );
-
-        attacker.state.has_bonus_knockback = false;
-    }
-}
-
 fn handle_collision_events(
     mut clients: Query<CombatQuery>,
-    arrows: Query<(&Velocity, &ProjectileOwner)>,
+    arrows: Query<(&Velocity, &ProjectileOwner, Has<CriticalArrow>, &ArrowEnchantments)>,
     mut collisions: EventReader<ProjectileCollisionEvent>,
     mut deaths: EventWriter<DeathEvent>,
+    knockback: Res<KnockbackProfile>,
 ) {
     for event in collisions.read() {
-        if let Ok((vel, owner)) = arrows.get(event.arrow)
+        if let Ok((vel, owner, critical, enchantments)) = arrows.get(event.projectile)
             && let Ok([mut attacker, mut victim]) = clients.get_many_mut([owner.0, event.player])
         {
             if attacker.gamestate.team == victim.gamestate.team {
                 continue;
             }
 
-            // TODO: Make the damage accurate
             let dmg = calc_dmg(
-                0.13 * vel.0.length(),
-                victim.inv.slot(5).item,
-                victim.inv.slot(6).item,
-                victim.inv.slot(7).item,
-                victim.inv.slot(8).item
+                arrow_damage(vel.0, critical, enchantments),
+                victim.inv.slot(5),
+                victim.inv.slot(6),
+                victim.inv.slot(7),
+                victim.inv.slot(8),
+                DamageType::Projectile,
             );
 
-            damage_player(
+            if damage_player(
                 &mut attacker,
                 &mut victim,
                 dmg,
-                vel.0.normalize().with_y(0.0) * 0.6 * 20.0, // TODO: Make the knockback accurate
-                &mut deaths,
-            );
+                arrow_knockback(vel.0, knockback.horizontal, knockback.vertical, enchantments),
+                &knockback,
+            ) {
+                deaths.send(DeathEvent(victim.entity, true));
+            }
             attacker.client.play_sound(
                 Sound::EntityArrowHitPlayer,
                 SoundCategory::Player,
@@ -507,6 +412,8 @@ fn handle_death(
     mut deaths: EventReader<DeathEvent>,
     mut broadcasts: EventWriter<MessageEvent>,
     config: Res<BridgeConfig>,
+    settings: Res<GameSettings>,
+    kits: Res<Kits>,
 ) {
     let mut killers = Vec::new();
     for DeathEvent(entity, show) in deaths.read() {
@@ -531,7 +438,7 @@ fn handle_death(
                     killers.push(last_attacker);
                 }
             }
-            let spawn = &config.worlds[map_index.0].spawns[gamestate.team as usize];
+            let spawn = config.worlds[map_index.0].spawn(gamestate.team, gamestate.slot, settings.team_size);
             pos.0 = spawn.pos.into();
             look.yaw = spawn.rot[0];
             look.pitch = spawn.rot[1];
@@ -541,7 +448,7 @@ fn handle_death(
             for slot in 0..inventory.slot_count() {
                 inventory.set_slot(slot, ItemStack::EMPTY);
             }
-            fill_inventory(&mut inventory, gamestate.team);
+            kits.give("default", &mut inventory, gamestate.team);
             if *show {
                 broadcasts.send(MessageEvent {
                     game: game_id,
@@ -577,11 +484,11 @@ fn handle_death(
 
 fn handle_score(
     clients: Query<(&Username, &PlayerGameState), With<Client>>,
-    mut games: Query<(&Entities, &mut GameStage, &mut GameTime, &mut GameData)>,
+    mut games: Query<(&Entities, &mut TeamScores)>,
     mut scores: EventReader<ScoreEvent>,
     mut deaths: EventWriter<DeathEvent>,
     mut broadcasts: EventWriter<MessageEvent>,
-    mut gamestage: EventWriter<GameStageEvent>,
+    mut new_round: EventWriter<NewRoundEvent>,
     mut end_game: EventWriter<EndGameEvent>,
 ) {
     for ScoreEvent(player) in scores.read() {
@@ -591,15 +498,12 @@ fn handle_score(
         let Some(game) = gamestate.game_id else {
             continue;
         };
-        let Ok((entities, mut stage, mut time, mut data)) = games.get_mut(game) else {
+        let Ok((entities, mut team_scores)) = games.get_mut(game) else {
             continue;
         };
         let team = gamestate.team as usize;
-        let mut score = 0;
-        if let Some(DataValue::Int(old_score)) = data.0.get(&(team)) {
-            score = *old_score + 1;
-        }
-        data.0.insert(team, DataValue::Int(score));
+        team_scores.add(gamestate.team, 1);
+        let score = team_scores.0[team];
         for entity in entities.0.iter() {
             deaths.send(DeathEvent(*entity, false));
         }
@@ -618,34 +522,29 @@ fn handle_score(
         if score >= 5 {
             end_game.send(EndGameEvent {
                 game_id: game,
-                loser: if team == 0 { 1 } else { 0 },
+                result: GameResult::Ranking(vec![team as u8]),
             });
         } else {
-            time.0 = SystemTime::now(); // TODO: Replace with a better solution because the game time is not supposed to keep track of the entire game duration
-            stage.0 = 0;
-            gamestage.send(GameStageEvent {
-                game_id: game,
-                stage: 0,
-            });
+            new_round.send(NewRoundEvent(game));
         }
     }
 }
 
 // TODO: Optimize
 fn update_scoreboard(
-    games: Query<(Ref<GameData>, &Entities)>,
+    games: Query<(Ref<TeamScores>, &Entities)>,
     clients: Query<(&ScoreboardId, Ref<PlayerStatistics>)>,
     mut objectives: Query<&mut ObjectiveScores, With<Objective>>,
 ) {
-    for (data, entities) in games.iter() {
-        if let Some(DataValue::Int(blue)) = data.0.get(&0) && let Some(DataValue::Int(red)) = data.0.get(&1) {
-            for entity in entities.0.iter() {
-                if let Ok((scoreboard_id, stats)) = clients.get(*entity) && let Ok(mut scores) = objectives.get_mut(scoreboard_id.0) {
-                    if !data.is_changed() && !stats.is_changed() {
-                        continue;
-                    }
-                    *scores = gen_scores(&gen_text(*red, *blue, stats.kills, stats.deaths));
+    for (goals, entities) in games.iter() {
+        let blue = goals.0.first().copied().unwrap_or(0);
+        let red = goals.0.get(1).copied().unwrap_or(0);
+        for entity in entities.0.iter() {
+            if let Ok((scoreboard_id, stats)) = clients.get(*entity) && let Ok(mut scores) = objectives.get_mut(scoreboard_id.0) {
+                if !goals.is_changed() && !stats.is_changed() {
+                    continue;
                 }
+                *scores = gen_scores(&gen_text(red, blue, stats.kills, stats.deaths));
             }
         }
     }
@@ -678,57 +577,3 @@ fn gen_text(red: i32, blue: i32, kills: u16, deaths: u16) -> Vec<String> {
         format::WHITE.to_string()+"Deaths: "+itoa::Buffer::new().format(deaths),
     ]
 }
-
-fn damage_player(
-    attacker: &mut CombatQueryItem,
-    victim: &mut CombatQueryItem,
-    damage: f32,
-    velocity: Vec3,
-    deaths: &mut EventWriter<DeathEvent>,
-) {
-    let old_vel = Vec3::new(
-        (victim.pos.0.x - victim.old_pos.get().x) as f32,
-        (victim.pos.0.y - victim.old_pos.get().y) as f32,
-        (victim.pos.0.z - victim.old_pos.get().z) as f32,
-    );
-
-    victim.client.set_velocity(old_vel + velocity);
-
-    attacker.state.has_bonus_knockback = false;
-
-    victim.client.play_sound(
-        Sound::EntityPlayerHurt,
-        SoundCategory::Player,
-        victim.pos.0,
-        1.0,
-        1.0,
-    );
-    victim.client.write_packet(&DamageTiltS2c {
-        entity_id: VarInt(0),
-        yaw: 0.0,
-    });
-    attacker.client.play_sound(
-        Sound::EntityPlayerHurt,
-        SoundCategory::Player,
-        victim.pos.0,
-        1.0,
-        1.0,
-    );
-    attacker.client.write_packet(&DamageTiltS2c {
-        entity_id: VarInt(victim.id.get()),
-        yaw: 0.0,
-    });
-
-    victim.state.last_attacker = Some(attacker.entity);
-
-    let mut new_damage = damage;
-    if victim.absorption.0 > 0.0 {
-        new_damage -= damage.min(victim.absorption.0);
-        victim.absorption.0 -= damage.min(victim.absorption.0);
-    }
-    if victim.health.0 <= new_damage {
-        deaths.send(DeathEvent(victim.entity, true));
-    } else {
-        victim.health.0 -= new_damage;
-    }
-}
\ No newline at end of file
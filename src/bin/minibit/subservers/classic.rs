@@ -1,18 +1,9 @@
-#![allow(clippy::type_complexity)]
-
 use std::marker::PhantomData;
-use bevy_ecs::query::QueryData;
-use minibit_lib::duels::{CombatState, DefaultDuelsConfig, DuelsPlugin, EndGameEvent, Entities, PlayerGameState, StartGameEvent};
-use valence::entity::living::Health;
-use valence::entity::Velocity;
-use valence::entity::{EntityId, EntityStatuses};
-use valence::math::Vec3Swizzles;
+use minibit_lib::combat::{CombatPlugin, DamageMode};
+use minibit_lib::duels::{DefaultDuelsConfig, DuelsPlugin, Entities, PlayerGameState, StartGameEvent};
+use minibit_lib::kits::Kits;
+use minibit_lib::throwables::ThrowablePlugin;
 use valence::prelude::*;
-use valence::protocol::packets::play::DamageTiltS2c;
-use valence::protocol::sound::SoundCategory;
-use valence::protocol::Sound;
-use valence::protocol::VarInt;
-use valence::protocol::WritePacket;
 use minibit_lib::duels::oob::{OobMode, OobPlugin};
 use crate::ServerConfig;
 
@@ -22,6 +13,8 @@ pub fn main(config: ServerConfig) {
             path: config.path,
             network_config: config.network,
             default_gamemode: GameMode::Adventure,
+            team_size: 1,
+            team_count: 2,
             copy_map: false,
             phantom: PhantomData
         })
@@ -30,21 +23,26 @@ pub fn main(config: ServerConfig) {
             mode: OobMode::GameEndEvent,
             bounds_y: 0.0..,
         })
-        .add_systems(EventLoopUpdate, handle_combat_events)
+        .add_plugins(CombatPlugin {
+            damage: DamageMode::Fixed(5.83),
+            deaths_end_game: true,
+        })
+        .add_plugins(ThrowablePlugin::default())
         .add_systems(Update, (start_game, end_game))
         .run();
 }
 
 fn start_game(
-    mut clients: Query<&mut Inventory>,
+    mut clients: Query<(&mut Inventory, &PlayerGameState)>,
     games: Query<&Entities>,
     mut start_game: EventReader<StartGameEvent>,
+    kits: Res<Kits>,
 ) {
     for event in start_game.read() {
         if let Ok(entities) = games.get(event.0) {
             for entity in entities.0.iter() {
-                if let Ok(mut inv) = clients.get_mut(*entity) {
-                    inv.set_slot(36, ItemStack::new(ItemKind::IronSword, 1, None));
+                if let Ok((mut inv, gamestate)) = clients.get_mut(*entity) {
+                    kits.give("default", &mut inv, gamestate.team);
                 }
             }
         }
@@ -68,107 +66,3 @@ fn end_game(
         }
     }
 }
-
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct CombatQuery {
-    client: &'static mut Client,
-    id: &'static EntityId,
-    pos: &'static Position,
-    vel: &'static mut Velocity,
-    health: &'static mut Health,
-    state: &'static mut CombatState,
-    statuses: &'static mut EntityStatuses,
-    gamestate: &'static PlayerGameState,
-}
-
-fn handle_combat_events(
-    server: Res<Server>,
-    mut clients: Query<CombatQuery>,
-    mut sprinting: EventReader<SprintEvent>,
-    mut interact_entity: EventReader<InteractEntityEvent>,
-    mut end_game: EventWriter<EndGameEvent>,
-) {
-    for &SprintEvent { client, state } in sprinting.read() {
-        if let Ok(mut client) = clients.get_mut(client) {
-            client.state.has_bonus_knockback = state == SprintState::Start;
-        }
-    }
-
-    for &InteractEntityEvent {
-        client: attacker_client,
-        entity: victim_client,
-        interact: interaction,
-        ..
-    } in interact_entity.read()
-    {
-        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
-        else {
-            continue;
-        };
-
-        if interaction != EntityInteraction::Attack
-            || server.current_tick() - victim.state.last_attacked_tick < 10
-            || attacker.gamestate.game_id != victim.gamestate.game_id
-        {
-            continue;
-        }
-
-        victim.state.last_attacked_tick = server.current_tick();
-
-        let victim_pos = victim.pos.0.xz();
-        let attacker_pos = attacker.pos.0.xz();
-
-        let dir = (victim_pos - attacker_pos).normalize().as_vec2();
-
-        let knockback_xz = if attacker.state.has_bonus_knockback {
-            18.0
-        } else {
-            8.0
-        };
-        let knockback_y = if attacker.state.has_bonus_knockback {
-            8.432
-        } else {
-            6.432
-        };
-
-        victim
-            .client
-            .set_velocity([dir.x * knockback_xz, knockback_y, dir.y * knockback_xz]);
-
-        let damage = 5.83;
-        if victim.health.0 > damage {
-            victim.health.0 -= damage;
-        } else {
-            end_game.send(EndGameEvent {
-                game_id: victim.gamestate.game_id.unwrap(),
-                loser: victim.gamestate.team,
-            });
-        }
-
-        attacker.state.has_bonus_knockback = false;
-
-        victim.client.play_sound(
-            Sound::EntityPlayerHurt,
-            SoundCategory::Player,
-            victim.pos.0,
-            1.0,
-            1.0,
-        );
-        victim.client.write_packet(&DamageTiltS2c {
-            entity_id: VarInt(0),
-            yaw: 0.0,
-        });
-        attacker.client.play_sound(
-            Sound::EntityPlayerHurt,
-            SoundCategory::Player,
-            victim.pos.0,
-            1.0,
-            1.0,
-        );
-        attacker.client.write_packet(&DamageTiltS2c {
-            entity_id: VarInt(victim.id.get()),
-            yaw: 0.0,
-        });
-    }
-}
@@ -6,10 +6,10 @@ use std::{
     marker::PhantomData,
     time::{Duration, SystemTime},
 };
-use minibit_lib::{config::{ConfigLoaderPlugin, WorldValue}, player::*, scopes::ScopePlugin};
+use minibit_lib::{anticheat::{AntiCheatPlugin, movement::MovementState}, chat::{ChatEvent, ChatPlugin, ChatSet}, config::{ConfigLoaderPlugin, WorldValue}, messaging::MessagingPlugin, network::{self, NetworkPlugin}, player::*, profile::ProfilePlugin, reports::ReportPlugin, scopes::ScopePlugin};
 use serde::Deserialize;
 use valence::{
-    entity::{living::Health, player::{PlayerEntityBundle, PlayerModelParts}}, event_loop::PacketEvent, inventory::{ClickSlotEvent, HeldItem}, message::{ChatMessageEvent, SendMessage}, nbt::{compound, List}, player_list::{DisplayName, Listed, PlayerListEntryBundle}, prelude::*, protocol::{packets::play::PlayerInteractItemC2s, sound::SoundCategory, Sound}
+    entity::{living::Health, player::{PlayerEntityBundle, PlayerModelParts}}, event_loop::PacketEvent, inventory::{ClickSlotEvent, HeldItem}, message::SendMessage, nbt::{compound, List}, player_list::{DisplayName, Listed, PlayerListEntryBundle}, prelude::*, protocol::{packets::play::PlayerInteractItemC2s, sound::SoundCategory, Sound}
 };
 use valence_anvil::AnvilLevel;
 use minibit_lib::config::DataPath;
@@ -82,7 +82,7 @@ pub fn main(config: ServerConfig) {
             phantom: PhantomData,
         })
         .add_plugins(DefaultPlugins)
-        .add_plugins((ScopePlugin, commands::CommandPlugin, ScoreboardPlugin {
+        .add_plugins((AntiCheatPlugin, ScopePlugin, ProfilePlugin, ChatPlugin, NetworkPlugin, MessagingPlugin, ReportPlugin, commands::CommandPlugin, ScoreboardPlugin {
             name: "MINIBIT",
             text: vec!["Welcome to MiniBit!"],
             mode: ScoreboardMode::ServerWide,
@@ -100,7 +100,7 @@ pub fn main(config: ServerConfig) {
                 init_clients,
                 manage_players,
                 entity_interactions,
-                chat_message,
+                chat_message.after(ChatSet),
                 start_parkour,
                 manage_parkour,
                 execute_action,
@@ -267,12 +267,12 @@ fn init_clients(
 }
 
 fn manage_players(
-    mut clients: Query<(&mut Client, &mut Position, &HeadYaw), With<Client>>,
+    mut clients: Query<(&mut Client, &mut Position, &HeadYaw, &mut MovementState), With<Client>>,
     mut layers: Query<&mut ChunkLayer>,
     config: Res<LobbyConfig>,
 ) {
     let layer = layers.single_mut();
-    for (mut client, mut pos, yaw) in clients.iter_mut() {
+    for (mut client, mut pos, yaw, mut movement) in clients.iter_mut() {
         if pos.0.y < 0.0 {
             pos.set(config.world.spawns[0].pos);
         }
@@ -292,7 +292,9 @@ fn manage_players(
                 1.0,
             );
             let yaw = yaw.0.to_radians();
-            client.set_velocity(Vec3::new(-yaw.sin() * 65.0, 30.0, yaw.cos() * 65.0));
+            let velocity = Vec3::new(-yaw.sin() * 65.0, 30.0, yaw.cos() * 65.0);
+            client.set_velocity(velocity);
+            movement.add_velocity(velocity);
         }
     }
 }
@@ -375,19 +377,12 @@ fn handle_slot_click(
 }
 
 fn chat_message(
-    usernames: Query<&Username>,
     mut clients: Query<&mut Client>,
-    mut events: EventReader<ChatMessageEvent>,
+    mut events: EventReader<ChatEvent>,
 ) {
     for event in events.read() {
-        let Ok(username) = usernames.get(event.client) else {
-            continue;
-        };
         for mut client in clients.iter_mut() {
-            client.send_chat_message(
-                (String::new() + &username.0 + &String::from(": ") + &event.message)
-                    .color(Color::GRAY),
-            );
+            client.send_chat_message(event.text.clone());
         }
     }
 }
@@ -467,13 +462,7 @@ fn execute_action(
                     }
                 }
                 ActionType::Warp => {
-                    let mut payload: Vec<u8> = Vec::new();
-                    payload.extend_from_slice("1".as_bytes());
-                    payload.push(0);
-                    payload.extend_from_slice(username.0.to_string().as_bytes());
-                    payload.push(0);
-                    payload.extend_from_slice(event.args[0].as_bytes());
-                    client.send_custom_payload(ident!("minibit:main"), &payload);
+                    network::warp(&mut client, &username.0, &event.args[0]);
                 }
                 ActionType::None => {}
             }
@@ -5,6 +5,10 @@ use std::marker::PhantomData;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use minibit_lib::config::{ConfigLoaderPlugin, EmptyConfig};
+use minibit_lib::messaging::MessagingPlugin;
+use minibit_lib::reports::ReportPlugin;
+use minibit_lib::network::NetworkPlugin;
+use minibit_lib::scopes::ScopePlugin;
 use valence::prelude::*;
 use valence::protocol::sound::{Sound, SoundCategory};
 use valence::spawn::IsFlat;
@@ -31,6 +35,7 @@ pub fn main(config: ServerConfig) {
             phantom: PhantomData
         })
         .add_plugins(DefaultPlugins)
+        .add_plugins((ScopePlugin, NetworkPlugin, MessagingPlugin, ReportPlugin))
         .add_systems(
             Update,
             (
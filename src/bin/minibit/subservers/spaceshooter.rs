@@ -2,6 +2,10 @@
 
 use std::marker::PhantomData;
 use minibit_lib::config::{ConfigLoaderPlugin, EmptyConfig};
+use minibit_lib::messaging::MessagingPlugin;
+use minibit_lib::reports::ReportPlugin;
+use minibit_lib::network::NetworkPlugin;
+use minibit_lib::scopes::ScopePlugin;
 use valence::{
     entity::{
         entity::NoGravity, falling_block::{FallingBlockEntity, FallingBlockEntityBundle}, ObjectData, Velocity
@@ -26,6 +30,7 @@ pub fn main(config: ServerConfig) {
             phantom: PhantomData
         })
         .add_plugins(DefaultPlugins)
+        .add_plugins((ScopePlugin, NetworkPlugin, MessagingPlugin, ReportPlugin))
         .add_systems(
             Update,
             (
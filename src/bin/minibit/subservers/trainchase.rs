@@ -5,6 +5,10 @@ use std::{
     time::{Duration, Instant},
 };
 use minibit_lib::config::{ConfigLoaderPlugin, EmptyConfig};
+use minibit_lib::messaging::MessagingPlugin;
+use minibit_lib::reports::ReportPlugin;
+use minibit_lib::network::NetworkPlugin;
+use minibit_lib::scopes::ScopePlugin;
 use valence::{
     entity::{
         entity::{self, NoGravity},
@@ -71,6 +75,7 @@ pub fn main(config: ServerConfig) {
             phantom: PhantomData,
         })
         .add_plugins(DefaultPlugins)
+        .add_plugins((ScopePlugin, NetworkPlugin, MessagingPlugin, ReportPlugin))
         .insert_resource(Tick(0))
         .add_systems(EventLoopUpdate, handle_interactions)
         .add_systems(
@@ -0,0 +1,204 @@
+#![allow(clippy::too_many_arguments)]
+
+use super::{AntiCheatSet, AntiCheatSettings, ViolationEvent};
+use parry3d::{
+    math::{Point, Vector},
+    na::{self, Isometry3},
+    query::{PointQuery, Ray, RayCast},
+    shape::Cuboid,
+};
+use std::collections::VecDeque;
+use valence::prelude::*;
+
+const EYE_HEIGHT: f64 = 1.62;
+
+/// Attacks that failed a check this event loop iteration, as (attacker, victim). Combat handlers
+/// should ignore these.
+#[derive(Resource, Default)]
+pub struct RejectedAttacks(Vec<(Entity, Entity)>);
+
+impl RejectedAttacks {
+    pub fn contains(&self, attacker: Entity, victim: Entity) -> bool {
+        self.0.contains(&(attacker, victim))
+    }
+}
+
+#[derive(Component, Default)]
+struct AttackHistory(VecDeque<i64>);
+
+pub struct CombatChecksPlugin;
+
+impl Plugin for CombatChecksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RejectedAttacks>()
+            .add_systems(Update, init_clients)
+            .add_systems(EventLoopUpdate, check_attacks.in_set(AntiCheatSet));
+    }
+}
+
+fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
+    for entity in clients.iter() {
+        commands.entity(entity).insert(AttackHistory::default());
+    }
+}
+
+fn check_attacks(
+    mut attackers: Query<(&Position, &VisibleChunkLayer, &mut AttackHistory)>,
+    victims: Query<(&Position, &OldPosition)>,
+    layers: Query<&ChunkLayer>,
+    mut interact_entity: EventReader<InteractEntityEvent>,
+    mut violations: EventWriter<ViolationEvent>,
+    mut rejected: ResMut<RejectedAttacks>,
+    settings: Res<AntiCheatSettings>,
+    server: Res<Server>,
+) {
+    rejected.0.clear();
+    for event in interact_entity.read() {
+        if event.interact != EntityInteraction::Attack {
+            continue;
+        }
+        let (Ok((attacker_pos, layer, mut history)), Ok((victim_pos, victim_old_pos))) =
+            (attackers.get_mut(event.client), victims.get(event.entity))
+        else {
+            continue;
+        };
+
+        let tick = server.current_tick();
+        while history.0.front().is_some_and(|&t| tick - t >= 20) {
+            history.0.pop_front();
+        }
+        history.0.push_back(tick);
+        if history.0.len() > settings.max_cps {
+            rejected.0.push((event.client, event.entity));
+            violations.send(ViolationEvent {
+                client: event.client,
+                check: "cps",
+                amount: 1.0,
+                details: format!("{} clicks in the last second", history.0.len()),
+            });
+            continue;
+        }
+
+        // The victim may have moved since the attacker saw them, so use whichever position is closer
+        let reach = reach_distance(attacker_pos.0, victim_pos.0)
+            .min(reach_distance(attacker_pos.0, victim_old_pos.get()));
+        if reach > settings.max_reach {
+            rejected.0.push((event.client, event.entity));
+            violations.send(ViolationEvent {
+                client: event.client,
+                check: "reach",
+                amount: 1.0 + (reach - settings.max_reach) as f32 * 2.0,
+                details: format!("{:.2} blocks", reach),
+            });
+            continue;
+        }
+
+        if settings.check_walls
+            && let Ok(layer) = layers.get(layer.0)
+        {
+            let eyes = eye_position(attacker_pos.0);
+            let targets = [
+                closest_hitbox_point(eyes, victim_pos.0),
+                victim_pos.0 + DVec3::new(0.0, 0.9, 0.0),
+                victim_pos.0 + DVec3::new(0.0, EYE_HEIGHT, 0.0),
+            ];
+            if targets
+                .iter()
+                .all(|&target| is_obstructed(layer, eyes, target))
+            {
+                rejected.0.push((event.client, event.entity));
+                violations.send(ViolationEvent {
+                    client: event.client,
+                    check: "walls",
+                    amount: 2.0,
+                    details: "attacked through a block".to_string(),
+                });
+            }
+        }
+    }
+}
+
+pub fn eye_position(pos: DVec3) -> DVec3 {
+    pos + DVec3::new(0.0, EYE_HEIGHT, 0.0)
+}
+
+fn player_hitbox(pos: DVec3) -> (Cuboid, Isometry3<f32>) {
+    (
+        Cuboid::new(Vector::new(0.3, 0.9, 0.3)),
+        Isometry3::new(
+            Vector::new(pos.x as f32, pos.y as f32 + 0.9, pos.z as f32),
+            na::zero(),
+        ),
+    )
+}
+
+fn closest_hitbox_point(eyes: DVec3, victim_pos: DVec3) -> DVec3 {
+    let (shape, iso) = player_hitbox(victim_pos);
+    let point = shape
+        .project_point(
+            &iso,
+            &Point::new(eyes.x as f32, eyes.y as f32, eyes.z as f32),
+            true,
+        )
+        .point;
+    DVec3::new(point.x as f64, point.y as f64, point.z as f64)
+}
+
+/// Distance from the attacker's eyes to the closest point of the victim's hitbox.
+pub fn reach_distance(attacker_pos: DVec3, victim_pos: DVec3) -> f64 {
+    let eyes = eye_position(attacker_pos);
+    let (shape, iso) = player_hitbox(victim_pos);
+    shape.distance_to_point(
+        &iso,
+        &Point::new(eyes.x as f32, eyes.y as f32, eyes.z as f32),
+        true,
+    ) as f64
+}
+
+fn is_obstructed(layer: &ChunkLayer, from: DVec3, to: DVec3) -> bool {
+    let length = from.distance(to);
+    if length < 0.01 {
+        return false;
+    }
+    let dir = (to - from) / length;
+    let ray = Ray::new(
+        Point::new(from.x as f32, from.y as f32, from.z as f32),
+        Vector::new(dir.x as f32, dir.y as f32, dir.z as f32),
+    );
+
+    let mut checked = Vec::new();
+    let steps = (length / 0.25).ceil() as usize;
+    for i in 0..=steps {
+        let point = from + dir * (length * i as f64 / steps as f64);
+        let block_pos = BlockPos::new(
+            point.x.floor() as i32,
+            point.y.floor() as i32,
+            point.z.floor() as i32,
+        );
+        if checked.contains(&block_pos) {
+            continue;
+        }
+        checked.push(block_pos);
+        let Some(block) = layer.block(block_pos) else {
+            continue;
+        };
+
+        let offset = DVec3::new(block_pos.x as f64, block_pos.y as f64, block_pos.z as f64);
+        for aabb in block.state.collision_shapes() {
+            let half = (aabb.max() - aabb.min()) / 2.0;
+            let center = aabb.min() + half + offset;
+            let shape = Cuboid::new(Vector::new(half.x as f32, half.y as f32, half.z as f32));
+            let iso = Isometry3::new(
+                Vector::new(center.x as f32, center.y as f32, center.z as f32),
+                na::zero(),
+            );
+            if shape
+                .cast_ray(&iso, &ray, length as f32 - 0.01, true)
+                .is_some()
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
@@ -0,0 +1,182 @@
+pub mod combat;
+pub mod movement;
+
+use crate::config::{DataPath, ServerName, load_section};
+use crate::network::{self, NetworkEvent, NetworkMessage};
+use crate::reports::{SpectateLinks, spectate_button};
+use crate::scopes::has_scope;
+use serde::Deserialize;
+use std::time::Instant;
+use valence::log::debug;
+use valence::{
+    command::{CommandScopeRegistry, scopes::CommandScopes},
+    prelude::*,
+};
+
+const ALERT_SCOPE: &str = "minibit.anticheat.alerts";
+
+/// Systems that validate player input. Gameplay systems that act on the same events should run
+/// after this set.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AntiCheatSet;
+
+#[derive(Resource, Deserialize)]
+#[serde(default)]
+pub struct AntiCheatSettings {
+    pub max_reach: f64,
+    pub max_cps: usize,
+    pub check_walls: bool,
+    pub check_movement: bool,
+    pub max_speed: f64,
+    pub max_step: f64,
+    pub max_jump_height: f64,
+    pub max_air_ticks: i64,
+    pub alert_level: f32,
+    pub kick_level: f32,
+    pub decay_per_second: f32,
+}
+
+impl Default for AntiCheatSettings {
+    fn default() -> Self {
+        Self {
+            max_reach: 3.4,
+            max_cps: 16,
+            check_walls: true,
+            check_movement: true,
+            max_speed: 0.75,
+            max_step: 0.6,
+            max_jump_height: 1.35,
+            max_air_ticks: 30,
+            alert_level: 10.0,
+            kick_level: 40.0,
+            decay_per_second: 0.5,
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct ViolationEvent {
+    pub client: Entity,
+    pub check: &'static str,
+    pub amount: f32,
+    pub details: String,
+}
+
+#[derive(Component, Default)]
+pub struct Violations {
+    pub level: f32,
+    last_alert: Option<Instant>,
+}
+
+pub struct AntiCheatPlugin;
+
+impl Plugin for AntiCheatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ViolationEvent>()
+            .add_plugins((combat::CombatChecksPlugin, movement::MovementChecksPlugin))
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    init_clients,
+                    decay_violations,
+                    handle_violations,
+                    deliver_alerts,
+                ),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    commands.insert_resource(load_section::<AntiCheatSettings>(&data_path.0, "anticheat"));
+}
+
+fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
+    for entity in clients.iter() {
+        commands.entity(entity).insert(Violations::default());
+    }
+}
+
+fn decay_violations(mut clients: Query<&mut Violations>, settings: Res<AntiCheatSettings>) {
+    for mut violations in clients.iter_mut() {
+        if violations.level > 0.0 {
+            violations.level = (violations.level - settings.decay_per_second / 20.0).max(0.0);
+        }
+    }
+}
+
+fn handle_violations(
+    mut clients: Query<(&mut Client, &Username, &mut Violations)>,
+    mut events: EventReader<ViolationEvent>,
+    settings: Res<AntiCheatSettings>,
+    server_name: Res<ServerName>,
+) {
+    for event in events.read() {
+        let Ok((mut client, username, mut violations)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        violations.level += event.amount;
+        debug!(
+            "[{}] {} failed {} ({}), VL {:.1}",
+            server_name.0, username.0, event.check, event.details, violations.level
+        );
+
+        if settings.kick_level > 0.0 && violations.level >= settings.kick_level {
+            client.kill("You were kicked for suspicious activity.".color(Color::RED));
+            violations.level = 0.0;
+            continue;
+        }
+        if violations.level >= settings.alert_level
+            && violations
+                .last_alert
+                .is_none_or(|time| time.elapsed().as_secs() >= 5)
+        {
+            violations.last_alert = Some(Instant::now());
+            network::broadcast(NetworkMessage::Violation {
+                player: username.0.clone(),
+                check: event.check.to_string(),
+                details: event.details.clone(),
+                level: violations.level,
+                server: server_name.0.clone(),
+            });
+        }
+    }
+}
+
+fn deliver_alerts(
+    mut clients: Query<(&mut Client, &CommandScopes)>,
+    mut events: EventReader<NetworkEvent>,
+    registry: Res<CommandScopeRegistry>,
+    server_name: Res<ServerName>,
+    links: Option<Res<SpectateLinks>>,
+) {
+    for NetworkEvent(message) in events.read() {
+        let NetworkMessage::Violation {
+            player,
+            check,
+            details,
+            level,
+            server,
+        } = message
+        else {
+            continue;
+        };
+        for (mut client, scopes) in clients.iter_mut() {
+            if !has_scope(&registry, scopes, ALERT_SCOPE) {
+                continue;
+            }
+            client.send_chat_message(
+                Text::from("[AC] ").color(Color::DARK_RED)
+                    + Text::from(format!(
+                        "{} failed {} on {} (VL {:.1}) ",
+                        player, check, server, level
+                    ))
+                    .color(Color::GRAY)
+                    + Text::from("[?] ")
+                        .color(Color::YELLOW)
+                        .on_hover_show_text(details.clone())
+                    + spectate_button(player, *server == server_name.0 && links.is_some()),
+            );
+        }
+    }
+}
@@ -0,0 +1,229 @@
+#![allow(clippy::type_complexity)]
+
+use super::{AntiCheatSet, AntiCheatSettings, ViolationEvent};
+use valence::{math::Vec3Swizzles, movement::MovementEvent, prelude::*};
+
+const GRAVITY: f64 = 0.08;
+const GROUND_TOLERANCE: f64 = 0.1;
+
+/// Tracks the last valid position of a player and the velocity the server gave them. Anything that
+/// calls `Client::set_velocity` on a player should also call `add_velocity` so the resulting
+/// movement isn't flagged.
+#[derive(Component, Default)]
+pub struct MovementState {
+    last_position: DVec3,
+    last_valid: DVec3,
+    air_start: Option<(i64, f64)>,
+    velocity: DVec3,
+    jump_allowance: f64,
+    velocity_ticks: u32,
+    lag_back: Option<DVec3>,
+    falling: bool,
+}
+
+impl MovementState {
+    /// `velocity` is in blocks per second, the same as `Client::set_velocity`.
+    pub fn add_velocity(&mut self, velocity: impl Into<Vec3>) {
+        let velocity = velocity.into().as_dvec3() / 20.0;
+        self.velocity = self.velocity.abs().max(velocity.abs());
+        if velocity.y > 0.0 {
+            self.jump_allowance += velocity.y * velocity.y / (2.0 * GRAVITY);
+        }
+        self.velocity_ticks = 40;
+    }
+
+    /// Whether the player was in the air and moving down on their last movement, which is what 1.8
+    /// requires for a critical hit.
+    pub fn is_falling(&self) -> bool {
+        self.falling
+    }
+
+    fn reset(&mut self, position: DVec3) {
+        self.last_position = position;
+        self.last_valid = position;
+        self.air_start = None;
+    }
+}
+
+pub struct MovementChecksPlugin;
+
+impl Plugin for MovementChecksPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (init_clients, decay_velocity, apply_lag_back))
+            .add_systems(EventLoopUpdate, check_movement.in_set(AntiCheatSet));
+    }
+}
+
+fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
+    for entity in clients.iter() {
+        commands.entity(entity).insert(MovementState::default());
+    }
+}
+
+fn decay_velocity(mut clients: Query<&mut MovementState>) {
+    for mut state in clients.iter_mut() {
+        if state.velocity_ticks == 0 {
+            continue;
+        }
+        state.velocity_ticks -= 1;
+        state.velocity *= 0.91;
+        if state.velocity_ticks == 0 {
+            state.velocity = DVec3::ZERO;
+        }
+    }
+}
+
+fn apply_lag_back(mut clients: Query<(&mut Position, &mut MovementState)>) {
+    for (mut pos, mut state) in clients.iter_mut() {
+        if let Some(position) = state.lag_back.take() {
+            pos.set(position);
+            state.reset(position);
+        }
+    }
+}
+
+fn check_movement(
+    mut clients: Query<(&GameMode, &VisibleChunkLayer, &mut MovementState)>,
+    layers: Query<&ChunkLayer>,
+    mut movements: EventReader<MovementEvent>,
+    mut violations: EventWriter<ViolationEvent>,
+    settings: Res<AntiCheatSettings>,
+    server: Res<Server>,
+) {
+    for event in movements.read() {
+        let Ok((mode, layer, mut state)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        if state.lag_back.is_some() {
+            continue;
+        }
+        // Teleports and respawns move the player without a movement event
+        if state.last_position != event.old_position {
+            state.reset(event.old_position);
+        }
+        state.last_position = event.position;
+
+        let Ok(layer) = layers.get(layer.0) else {
+            continue;
+        };
+        state.falling = !event.on_ground
+            && event.position.y < event.old_position.y
+            && !is_exempt(layer, event.position);
+        if !settings.check_movement
+            || matches!(*mode, GameMode::Creative | GameMode::Spectator)
+            || is_exempt(layer, event.position)
+        {
+            state.reset(event.position);
+            continue;
+        }
+
+        let tick = server.current_tick();
+        let delta = event.position - event.old_position;
+        let horizontal = delta.xz().length();
+        let on_ground = event.on_ground && is_supported(layer, event.position);
+
+        let mut failed = None;
+        if horizontal > settings.max_speed + state.velocity.xz().length() {
+            failed = Some(("speed", 1.0, format!("{:.2} blocks/tick", horizontal)));
+        } else if event.on_ground && !on_ground && delta.y < 0.0 {
+            failed = Some(("nofall", 1.0, "claimed to be on the ground".to_string()));
+        } else if delta.y > settings.max_step + state.velocity.y {
+            failed = Some((
+                "fly",
+                2.0,
+                format!("ascended {:.2} blocks in one tick", delta.y),
+            ));
+        }
+
+        if on_ground {
+            state.air_start = None;
+            if state.velocity_ticks == 0 {
+                state.jump_allowance = 0.0;
+            }
+        } else if failed.is_none() {
+            let (start_tick, start_y) =
+                *state.air_start.get_or_insert((tick, event.old_position.y));
+            let height = event.position.y - start_y;
+            if height > settings.max_jump_height + state.jump_allowance {
+                failed = Some(("fly", 2.0, format!("ascended {:.2} blocks", height)));
+            } else if tick - start_tick > settings.max_air_ticks
+                && delta.y >= 0.0
+                && state.velocity_ticks == 0
+            {
+                failed = Some((
+                    "fly",
+                    2.0,
+                    format!("in the air for {} ticks", tick - start_tick),
+                ));
+            }
+        }
+
+        match failed {
+            Some((check, amount, details)) => {
+                violations.send(ViolationEvent {
+                    client: event.client,
+                    check,
+                    amount,
+                    details,
+                });
+                state.lag_back = Some(state.last_valid);
+            }
+            None if on_ground => state.last_valid = event.position,
+            None => {}
+        }
+    }
+}
+
+/// Whether any block under the player's feet is close enough to stand on.
+fn is_supported(layer: &ChunkLayer, pos: DVec3) -> bool {
+    let min = pos - DVec3::new(0.3, 0.0, 0.3);
+    let max = pos + DVec3::new(0.3, 0.0, 0.3);
+    let feet = (pos.y - GROUND_TOLERANCE).floor() as i32;
+    for x in min.x.floor() as i32..=max.x.floor() as i32 {
+        for z in min.z.floor() as i32..=max.z.floor() as i32 {
+            // Fences and walls stick out of the block below
+            for y in feet - 1..=feet {
+                let Some(block) = layer.block([x, y, z]) else {
+                    continue;
+                };
+                let offset = DVec3::new(x as f64, y as f64, z as f64);
+                for aabb in block.state.collision_shapes() {
+                    let shape_min = aabb.min() + offset;
+                    let shape_max = aabb.max() + offset;
+                    if (pos.y - shape_max.y).abs() <= GROUND_TOLERANCE
+                        && shape_min.x < max.x
+                        && shape_max.x > min.x
+                        && shape_min.z < max.z
+                        && shape_max.z > min.z
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Liquids, climbable and bouncy blocks let players move in ways the checks don't model.
+fn is_exempt(layer: &ChunkLayer, pos: DVec3) -> bool {
+    [-0.5, 0.0, 1.0].iter().any(|dy| {
+        let Some(block) = layer.block(BlockPos::new(
+            pos.x.floor() as i32,
+            (pos.y + dy).floor() as i32,
+            pos.z.floor() as i32,
+        )) else {
+            return false;
+        };
+        block.state.is_liquid()
+            || matches!(
+                block.state.to_kind(),
+                BlockKind::Ladder
+                    | BlockKind::Vine
+                    | BlockKind::Scaffolding
+                    | BlockKind::Cobweb
+                    | BlockKind::SlimeBlock
+                    | BlockKind::HoneyBlock
+            )
+    })
+}
@@ -0,0 +1,287 @@
+use crate::color::format;
+use crate::config::{DataPath, load_section};
+use crate::profile::PlayerProfile;
+use crate::scopes::has_scope;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use valence::{
+    command::{CommandScopeRegistry, scopes::CommandScopes},
+    message::ChatMessageEvent,
+    player_list::DisplayName,
+    prelude::*,
+};
+
+const BYPASS_SCOPE: &str = "minibit.chat.bypass";
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChatSet;
+
+/// A chat message that made it through the filters. Subservers should broadcast these instead of
+/// reading `ChatMessageEvent` directly. `text` is the message run through the chat format.
+#[derive(Event)]
+pub struct ChatEvent {
+    pub client: Entity,
+    pub message: String,
+    pub text: String,
+}
+
+/// A message typed through a command rather than the chat box. It goes through the same filters
+/// as `ChatMessageEvent`.
+#[derive(Event)]
+pub struct ChatInputEvent {
+    pub client: Entity,
+    pub message: String,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+pub enum FilterAction {
+    Mask,
+    Reject,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ChatSettings {
+    pub max_messages: usize,
+    pub interval_secs: u64,
+    pub duplicate_secs: u64,
+    pub blocked_patterns: Vec<String>,
+    pub blocked_action: FilterAction,
+    pub caps_min_length: usize,
+    pub caps_max_ratio: f32,
+    pub block_links: bool,
+    pub format: String,
+    pub tab_format: String,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            max_messages: 4,
+            interval_secs: 5,
+            duplicate_secs: 30,
+            blocked_patterns: Vec::new(),
+            blocked_action: FilterAction::Mask,
+            caps_min_length: 6,
+            caps_max_ratio: 0.7,
+            block_links: true,
+            format: "\u{00A7}8[\u{00A7}7{level}\u{00A7}8] {guild}{rank}{color}{name}\u{00A7}7: {message}"
+                .to_string(),
+            tab_format: "{rank}{color}{name}".to_string(),
+        }
+    }
+}
+
+/// The chat and tab list formats of this subserver, for systems that need to format a message
+/// themselves, e.g. after stripping a channel prefix.
+#[derive(Resource)]
+pub struct ChatFormat {
+    pub format: String,
+    pub tab_format: String,
+}
+
+impl ChatFormat {
+    pub fn message(&self, profile: &PlayerProfile, username: &str, message: &str) -> String {
+        apply_format(&self.format, profile, username, message)
+    }
+
+    pub fn tab_name(&self, profile: &PlayerProfile, username: &str) -> String {
+        apply_format(&self.tab_format, profile, username, "")
+    }
+}
+
+#[derive(Resource)]
+struct ChatFilter {
+    settings: ChatSettings,
+    blocked: Vec<Regex>,
+    link: Regex,
+}
+
+#[derive(Component, Default)]
+struct ChatState {
+    recent: VecDeque<Instant>,
+    last_message: Option<(String, Instant)>,
+}
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ChatEvent>()
+            .add_event::<ChatInputEvent>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    init_clients,
+                    update_display_names,
+                    filter_messages.in_set(ChatSet),
+                ),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    let settings = load_section::<ChatSettings>(&data_path.0, "chat");
+    let blocked = settings
+        .blocked_patterns
+        .iter()
+        .map(|pattern| Regex::new(&(String::from("(?i)") + pattern)).unwrap())
+        .collect();
+
+    commands.insert_resource(ChatFormat {
+        format: settings.format.clone(),
+        tab_format: settings.tab_format.clone(),
+    });
+    commands.insert_resource(ChatFilter {
+        settings,
+        blocked,
+        link: Regex::new(
+            r"(?i)(https?://|www\.)\S+|\b[a-z0-9-]+\.(com|net|org|gg|io|me|tv|xyz|co|uk)\b",
+        )
+        .unwrap(),
+    });
+}
+
+fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
+    for entity in clients.iter() {
+        commands.entity(entity).insert(ChatState::default());
+    }
+}
+
+fn update_display_names(
+    mut clients: Query<
+        (&mut DisplayName, &PlayerProfile, &Username),
+        Or<(Changed<PlayerProfile>, Added<DisplayName>)>,
+    >,
+    chat_format: Res<ChatFormat>,
+) {
+    for (mut display_name, profile, username) in clients.iter_mut() {
+        display_name.0 = Some(chat_format.tab_name(profile, &username.0).into_text());
+    }
+}
+
+fn filter_messages(
+    mut clients: Query<(
+        &mut Client,
+        &mut ChatState,
+        &Username,
+        Option<&PlayerProfile>,
+        Option<&CommandScopes>,
+    )>,
+    mut events: EventReader<ChatMessageEvent>,
+    mut inputs: EventReader<ChatInputEvent>,
+    mut chat: EventWriter<ChatEvent>,
+    registry: Res<CommandScopeRegistry>,
+    filter: Res<ChatFilter>,
+    chat_format: Res<ChatFormat>,
+) {
+    let messages = events
+        .read()
+        .map(|event| (event.client, &*event.message))
+        .chain(
+            inputs
+                .read()
+                .map(|event| (event.client, event.message.as_str())),
+        );
+    for (entity, message) in messages {
+        let Ok((mut client, mut state, username, profile, scopes)) = clients.get_mut(entity) else {
+            continue;
+        };
+        let profile = profile.cloned().unwrap_or_default();
+        let message = message.trim();
+        if message.is_empty() {
+            continue;
+        }
+        let result = if scopes.is_some_and(|scopes| has_scope(&registry, scopes, BYPASS_SCOPE)) {
+            Ok(message.to_string())
+        } else {
+            filter.apply(message, &mut state)
+        };
+
+        match result {
+            Ok(message) => {
+                chat.send(ChatEvent {
+                    client: entity,
+                    text: chat_format.message(&profile, &username.0, &message),
+                    message,
+                });
+            }
+            Err(reason) => client.send_chat_message(reason.color(Color::RED)),
+        }
+    }
+}
+
+impl ChatFilter {
+    fn apply(&self, message: &str, state: &mut ChatState) -> Result<String, &'static str> {
+        let now = Instant::now();
+        let interval = Duration::from_secs(self.settings.interval_secs);
+        while state
+            .recent
+            .front()
+            .is_some_and(|time| now.duration_since(*time) > interval)
+        {
+            state.recent.pop_front();
+        }
+        if state.recent.len() >= self.settings.max_messages {
+            return Err("You are sending messages too quickly!");
+        }
+
+        let normalized = message.to_lowercase();
+        if let Some((last, time)) = &state.last_message
+            && *last == normalized
+            && now.duration_since(*time) < Duration::from_secs(self.settings.duplicate_secs)
+        {
+            return Err("You already sent that message!");
+        }
+
+        if self.settings.block_links && self.link.is_match(message) {
+            return Err("Links are not allowed in chat!");
+        }
+
+        let mut message = message.to_string();
+        for pattern in self.blocked.iter() {
+            if !pattern.is_match(&message) {
+                continue;
+            }
+            if self.settings.blocked_action == FilterAction::Reject {
+                return Err("Your message contains a blocked word!");
+            }
+            message = pattern
+                .replace_all(&message, |caps: &regex::Captures| {
+                    "*".repeat(caps[0].chars().count())
+                })
+                .into_owned();
+        }
+
+        let letters = message.chars().filter(|c| c.is_alphabetic()).count();
+        let upper = message.chars().filter(|c| c.is_uppercase()).count();
+        if letters >= self.settings.caps_min_length
+            && upper as f32 / letters as f32 > self.settings.caps_max_ratio
+        {
+            message = message.to_lowercase();
+        }
+
+        state.recent.push_back(now);
+        state.last_message = Some((normalized, now));
+
+        Ok(message)
+    }
+}
+
+fn apply_format(template: &str, profile: &PlayerProfile, username: &str, message: &str) -> String {
+    let guild = match &profile.guild_tag {
+        Some(tag) => format::YELLOW.to_string() + "[" + tag + "] ",
+        None => String::new(),
+    };
+    // The message goes last so players can't inject placeholders
+    template
+        .replace("{level}", itoa::Buffer::new().format(profile.level))
+        .replace("{guild}", &guild)
+        .replace("{rank}", &profile.rank_prefix)
+        .replace("{color}", &(String::from("\u{00A7}") + &profile.rank_color))
+        .replace("{name}", username)
+        .replace("{message}", message)
+}
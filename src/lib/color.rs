@@ -43,3 +43,28 @@ pub enum ArmorColors {
     Black = 1908001,
     Brown = 8606770,
 }
+
+impl ArmorColors {
+    /// Looks up a dye colour by its item name, e.g. `light_blue`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "red" => Self::Red,
+            "orange" => Self::Orange,
+            "yellow" => Self::Yellow,
+            "lime" => Self::Lime,
+            "green" => Self::Green,
+            "light_blue" => Self::LightBlue,
+            "cyan" => Self::Cyan,
+            "blue" => Self::Blue,
+            "purple" => Self::Purple,
+            "magenta" => Self::Magenta,
+            "pink" => Self::Pink,
+            "white" => Self::White,
+            "light_gray" => Self::LightGray,
+            "gray" => Self::Gray,
+            "black" => Self::Black,
+            "brown" => Self::Brown,
+            _ => return None,
+        })
+    }
+}
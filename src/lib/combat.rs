@@ -0,0 +1,283 @@
+#![allow(clippy::type_complexity)]
+
+use crate::anticheat::{AntiCheatSet, combat::RejectedAttacks, movement::MovementState};
+use crate::config::{DataPath, load_section};
+use crate::damage::{DamageType, calc_dmg_with_weapon, enchantment_knockback, enchantment_level};
+use crate::death::DeathEvent;
+use crate::duels::{CombatState, EndGameEvent, EndGameSet, GameResult, PlayerGameState};
+use bevy_ecs::query::QueryData;
+use serde::Deserialize;
+use valence::{
+    entity::{EntityId, living::Absorption, living::Health},
+    inventory::HeldItem,
+    math::Vec3Swizzles,
+    prelude::*,
+    protocol::{Sound, VarInt, WritePacket, packets::play::DamageTiltS2c, sound::SoundCategory},
+};
+
+/// Knockback in blocks per second, loaded from the `knockback` section of `config.json`.
+#[derive(Resource, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct KnockbackProfile {
+    pub horizontal: f32,
+    pub vertical: f32,
+    /// Used instead of the above for the first hit after the attacker starts sprinting.
+    pub sprint_horizontal: f32,
+    pub sprint_vertical: f32,
+    /// Adds the victim's current movement to the knockback instead of replacing it.
+    pub keep_momentum: bool,
+    /// Ticks after a hit during which the victim can't be hit again.
+    pub hit_cooldown: i64,
+}
+
+impl Default for KnockbackProfile {
+    fn default() -> Self {
+        Self {
+            horizontal: 8.0,
+            vertical: 6.432,
+            sprint_horizontal: 18.0,
+            sprint_vertical: 8.432,
+            keep_momentum: false,
+            hit_cooldown: 10,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DamageMode {
+    /// Hits only deal knockback.
+    None,
+    /// Every hit deals the same damage, regardless of weapon and armour.
+    Fixed(f32),
+    /// 1.8 weapon damage with crits and Sharpness, reduced by the victim's armour.
+    Armor,
+}
+
+#[derive(Resource)]
+struct CombatSettings {
+    damage: DamageMode,
+}
+
+/// Sent for every melee hit that went through. `damage` is 0 with `DamageMode::None`.
+#[derive(Event)]
+pub struct HitEvent {
+    pub attacker: Entity,
+    pub victim: Entity,
+    pub damage: f32,
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct CombatQuery {
+    pub entity: Entity,
+    pub client: &'static mut Client,
+    pub id: &'static EntityId,
+    pub pos: &'static Position,
+    pub old_pos: &'static OldPosition,
+    pub state: &'static mut CombatState,
+    pub movement: &'static mut MovementState,
+    pub gamestate: &'static PlayerGameState,
+    pub health: &'static mut Health,
+    pub absorption: &'static mut Absorption,
+    pub held_item: &'static HeldItem,
+    pub inv: &'static Inventory,
+}
+
+/// Handles melee hits between players in the same game and on different teams. Lethal hits
+/// send a `DeathEvent` and leave the victim's health for the minigame to reset.
+pub struct CombatPlugin {
+    pub damage: DamageMode,
+    /// Ends the game on any death, with the dead player's team losing. For minigames without
+    /// respawns.
+    pub deaths_end_game: bool,
+}
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CombatSettings {
+            damage: self.damage,
+        })
+        .add_event::<HitEvent>()
+        .add_event::<DeathEvent>()
+        .add_systems(Startup, setup)
+        .add_systems(
+            EventLoopUpdate,
+            (track_sprinting, handle_hits).chain().after(AntiCheatSet),
+        );
+        if self.deaths_end_game {
+            app.add_systems(PostUpdate, end_game_on_death.before(EndGameSet));
+        }
+    }
+}
+
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    commands.insert_resource(load_section::<KnockbackProfile>(&data_path.0, "knockback"));
+}
+
+fn track_sprinting(mut clients: Query<&mut CombatState>, mut sprinting: EventReader<SprintEvent>) {
+    for &SprintEvent { client, state } in sprinting.read() {
+        if let Ok(mut combat_state) = clients.get_mut(client) {
+            combat_state.has_bonus_knockback = state == SprintState::Start;
+        }
+    }
+}
+
+fn handle_hits(
+    server: Res<Server>,
+    rejected: Res<RejectedAttacks>,
+    mut clients: Query<CombatQuery>,
+    mut interact_entity: EventReader<InteractEntityEvent>,
+    mut hits: EventWriter<HitEvent>,
+    mut deaths: EventWriter<DeathEvent>,
+    knockback: Res<KnockbackProfile>,
+    settings: Res<CombatSettings>,
+) {
+    for &InteractEntityEvent {
+        client: attacker_client,
+        entity: victim_client,
+        interact: interaction,
+        ..
+    } in interact_entity.read()
+    {
+        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
+        else {
+            continue;
+        };
+
+        if interaction != EntityInteraction::Attack
+            || rejected.contains(attacker_client, victim_client)
+            || server.current_tick() - victim.state.last_attacked_tick < knockback.hit_cooldown
+            || attacker.gamestate.game_id.is_none()
+            || attacker.gamestate.game_id != victim.gamestate.game_id
+            || attacker.gamestate.team == victim.gamestate.team
+        {
+            continue;
+        }
+
+        victim.state.last_attacked_tick = server.current_tick();
+
+        let weapon = attacker.inv.slot(attacker.held_item.slot());
+        let dir = (victim.pos.0.xz() - attacker.pos.0.xz())
+            .normalize()
+            .as_vec2();
+        let (horizontal, vertical) = if attacker.state.has_bonus_knockback {
+            (knockback.sprint_horizontal, knockback.sprint_vertical)
+        } else {
+            (knockback.horizontal, knockback.vertical)
+        };
+        let (bonus_horizontal, bonus_vertical) =
+            enchantment_knockback(enchantment_level(weapon, "knockback"), DamageType::Melee);
+        let horizontal = horizontal + bonus_horizontal;
+        let vertical = vertical + bonus_vertical;
+
+        let damage = match settings.damage {
+            DamageMode::None => 0.0,
+            DamageMode::Fixed(damage) => damage,
+            DamageMode::Armor => calc_dmg_with_weapon(
+                weapon,
+                attacker.movement.is_falling(),
+                victim.inv.slot(5),
+                victim.inv.slot(6),
+                victim.inv.slot(7),
+                victim.inv.slot(8),
+            ),
+        };
+
+        // Only melee hits use up the sprint bonus
+        attacker.state.has_bonus_knockback = false;
+        let lethal = damage_player(
+            &mut attacker,
+            &mut victim,
+            damage,
+            Vec3::new(dir.x * horizontal, vertical, dir.y * horizontal),
+            &knockback,
+        );
+        hits.send(HitEvent {
+            attacker: attacker.entity,
+            victim: victim.entity,
+            damage,
+        });
+        if lethal {
+            deaths.send(DeathEvent(victim.entity, true));
+        }
+    }
+}
+
+fn end_game_on_death(
+    clients: Query<&PlayerGameState>,
+    mut deaths: EventReader<DeathEvent>,
+    mut end_game: EventWriter<EndGameEvent>,
+) {
+    for DeathEvent(entity, _) in deaths.read() {
+        let Ok(gamestate) = clients.get(*entity) else {
+            continue;
+        };
+        let Some(game_id) = gamestate.game_id else {
+            continue;
+        };
+        end_game.send(EndGameEvent {
+            game_id,
+            result: GameResult::Loser(gamestate.team),
+        });
+    }
+}
+
+/// Applies knockback, hurt effects and damage, taking absorption first. Returns whether the hit
+/// was lethal, in which case the victim's health is left untouched.
+pub fn damage_player(
+    attacker: &mut CombatQueryItem,
+    victim: &mut CombatQueryItem,
+    damage: f32,
+    velocity: Vec3,
+    knockback: &KnockbackProfile,
+) -> bool {
+    let velocity = if knockback.keep_momentum {
+        let old_vel = Vec3::new(
+            (victim.pos.0.x - victim.old_pos.get().x) as f32,
+            (victim.pos.0.y - victim.old_pos.get().y) as f32,
+            (victim.pos.0.z - victim.old_pos.get().z) as f32,
+        );
+        old_vel + velocity
+    } else {
+        velocity
+    };
+    victim.client.set_velocity(velocity);
+    victim.movement.add_velocity(velocity);
+
+    victim.client.play_sound(
+        Sound::EntityPlayerHurt,
+        SoundCategory::Player,
+        victim.pos.0,
+        1.0,
+        1.0,
+    );
+    victim.client.write_packet(&DamageTiltS2c {
+        entity_id: VarInt(0),
+        yaw: 0.0,
+    });
+    attacker.client.play_sound(
+        Sound::EntityPlayerHurt,
+        SoundCategory::Player,
+        victim.pos.0,
+        1.0,
+        1.0,
+    );
+    attacker.client.write_packet(&DamageTiltS2c {
+        entity_id: VarInt(victim.id.get()),
+        yaw: 0.0,
+    });
+
+    victim.state.last_attacker = Some(attacker.entity);
+
+    if damage <= 0.0 {
+        return false;
+    }
+    let absorbed = damage.min(victim.absorption.0);
+    victim.absorption.0 -= absorbed;
+    let damage = damage - absorbed;
+    if victim.health.0 <= damage {
+        return true;
+    }
+    victim.health.0 -= damage;
+    false
+}
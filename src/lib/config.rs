@@ -1,10 +1,11 @@
+use crate::db::DatabasePlugin;
 use ::serde::Deserialize;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 use std::{
     marker::PhantomData,
     net::{IpAddr, SocketAddr},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
 };
@@ -23,6 +24,10 @@ pub struct NetworkConfig {
     pub connection_mode: u8,
     #[serde(skip_deserializing)]
     pub forwarding_secret: String,
+    #[serde(skip_deserializing)]
+    pub server_name: String,
+    #[serde(skip_deserializing)]
+    pub database_url: Option<String>,
 }
 
 impl Default for NetworkConfig {
@@ -34,6 +39,8 @@ impl Default for NetworkConfig {
             connection_mode: 1,
             prevent_proxy_connections: false,
             forwarding_secret: "".to_string(),
+            server_name: "".to_string(),
+            database_url: None,
         }
     }
 }
@@ -47,6 +54,30 @@ pub struct WorldValue {
     pub x_chunks: [i32; 2],
     pub z_chunks: [i32; 2],
     pub spawns: Vec<SpawnValue>,
+    /// How likely the map is to be picked compared to the others. Defaults to 1.
+    #[serde(default)]
+    pub weight: Option<f64>,
+}
+
+impl WorldValue {
+    pub fn weight(&self) -> f64 {
+        self.weight.unwrap_or(1.0)
+    }
+
+    /// The world's folder name, which players use to pick the map.
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    /// Spawns are listed team by team, one per slot. Maps with fewer spawns than players reuse the
+    /// first spawn of each team, or the spawn at the team's index for maps that list one per team.
+    pub fn spawn(&self, team: u8, slot: u8, team_size: usize) -> &SpawnValue {
+        let first = team as usize * team_size;
+        self.spawns
+            .get(first + slot as usize)
+            .or_else(|| self.spawns.get(first))
+            .unwrap_or(&self.spawns[team as usize % self.spawns.len()])
+    }
 }
 
 #[derive(Deserialize)]
@@ -58,6 +89,21 @@ pub struct SpawnValue {
 #[derive(Resource)]
 pub struct DataPath(pub PathBuf);
 
+/// The name the proxy knows this subserver by, e.g. `lobby` or `bridge`.
+#[derive(Resource)]
+pub struct ServerName(pub String);
+
+/// Reads an optional top-level section of a subserver's `config.json`, falling back to the default
+/// if it is missing. Used by shared plugins that don't own the subserver's config type.
+pub fn load_section<S: DeserializeOwned + Default>(path: &Path, key: &str) -> S {
+    let data = std::fs::read_to_string(path.join("config.json")).unwrap();
+    let mut value = serde_json::from_str::<serde_json::Value>(&data).unwrap();
+    match value.get_mut(key) {
+        Some(section) => serde_json::from_value(section.take()).unwrap(),
+        None => S::default(),
+    }
+}
+
 pub struct ConfigLoaderPlugin<T: DeserializeOwned> {
     pub path: PathBuf,
     pub network_config: NetworkConfig,
@@ -92,6 +138,11 @@ impl<T: Resource + DeserializeOwned + Sync + Send + 'static> Plugin for ConfigLo
             ..Default::default()
         })
         .insert_resource(config)
-        .insert_resource(DataPath(self.path.clone()));
+        .insert_resource(DataPath(self.path.clone()))
+        .insert_resource(ServerName(self.network_config.server_name.clone()));
+
+        if let Some(url) = &self.network_config.database_url {
+            app.add_plugins(DatabasePlugin::new(url.clone()));
+        }
     }
 }
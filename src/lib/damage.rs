@@ -1,6 +1,38 @@
 #![allow(dead_code)]
 
-use valence::ItemKind;
+use valence::{
+    ItemKind, ItemStack,
+    nbt::{List, Value},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DamageType {
+    Melee,
+    Projectile,
+}
+
+/// Level of an enchantment like `sharpness` in the item's `Enchantments` NBT, or 0 if it has none.
+pub fn enchantment_level(stack: &ItemStack, enchantment: &str) -> i16 {
+    let Some(Value::List(List::Compound(enchantments))) =
+        stack.nbt.as_ref().and_then(|nbt| nbt.get("Enchantments"))
+    else {
+        return 0;
+    };
+    enchantments
+        .iter()
+        .filter(|ench| match ench.get("id") {
+            Some(Value::String(id)) => id.trim_start_matches("minecraft:") == enchantment,
+            _ => false,
+        })
+        .map(|ench| match ench.get("lvl") {
+            Some(Value::Short(lvl)) => *lvl,
+            Some(Value::Int(lvl)) => *lvl as i16,
+            Some(Value::Byte(lvl)) => *lvl as i16,
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
 
 // Uses 1.8 damage
 pub fn item_dmg(item: ItemKind) -> f32 {
@@ -65,7 +97,86 @@ pub fn armor_toughness(item: ItemKind) -> f32 {
     }
 }
 
+/// Melee damage before armour. Critical hits multiply the base damage by 1.5 and Sharpness adds
+/// 1.25 per level on top.
+pub fn weapon_dmg(weapon: &ItemStack, critical: bool) -> f32 {
+    let base = item_dmg(weapon.item);
+    let base = if critical { base * 1.5 } else { base };
+    base + 1.25 * enchantment_level(weapon, "sharpness") as f32
+}
+
+/// Damage per block per tick of an arrow's speed. Power adds 0.5 per level plus 0.5.
+pub fn arrow_base_dmg(power: i16) -> f32 {
+    if power > 0 {
+        2.0 + power as f32 * 0.5 + 0.5
+    } else {
+        2.0
+    }
+}
+
+/// Extra knockback in blocks per second, as (horizontal, vertical), from Knockback on a weapon or
+/// Punch on a bow.
+pub fn enchantment_knockback(level: i16, damage_type: DamageType) -> (f32, f32) {
+    if level <= 0 {
+        return (0.0, 0.0);
+    }
+    let per_level = match damage_type {
+        DamageType::Melee => 0.5,
+        DamageType::Projectile => 0.6,
+    };
+    (level as f32 * per_level * 20.0, 0.1 * 20.0)
+}
+
+/// Enchantment protection factor of one armour piece against the given damage type.
+fn piece_epf(piece: &ItemStack, damage_type: DamageType) -> i32 {
+    let epf = |level: i16, multiplier: f32| {
+        if level <= 0 {
+            return 0;
+        }
+        ((6 + level as i32 * level as i32) as f32 / 3.0 * multiplier).floor() as i32
+    };
+    let protection = epf(enchantment_level(piece, "protection"), 0.75);
+    match damage_type {
+        DamageType::Melee => protection,
+        DamageType::Projectile => {
+            protection + epf(enchantment_level(piece, "projectile_protection"), 1.5)
+        }
+    }
+}
+
+/// Total enchantment protection factor of a set of armour, capped at 25.
+pub fn protection_epf(armor: [&ItemStack; 4], damage_type: DamageType) -> i32 {
+    armor
+        .iter()
+        .map(|piece| piece_epf(piece, damage_type))
+        .sum::<i32>()
+        .clamp(0, 25)
+}
+
+/// Reduces damage by the protection factor the way 1.8 does: half the EPF rounded up, plus `roll`
+/// which is random between 0 and half the EPF, at 4% each and at most 80%.
+pub fn apply_epf(dmg: f32, epf: i32, roll: i32) -> f32 {
+    if epf <= 0 {
+        return dmg;
+    }
+    let reduction = ((epf + 1) / 2 + roll).min(20);
+    dmg * (25 - reduction) as f32 / 25.0
+}
+
 pub fn calc_dmg(
+    dmg: f32,
+    helmet: &ItemStack,
+    chestplate: &ItemStack,
+    leggings: &ItemStack,
+    boots: &ItemStack,
+    damage_type: DamageType,
+) -> f32 {
+    let dmg = calc_armor_dmg(dmg, helmet.item, chestplate.item, leggings.item, boots.item);
+    let epf = protection_epf([helmet, chestplate, leggings, boots], damage_type);
+    apply_epf(dmg, epf, fastrand::i32(0..=epf / 2))
+}
+
+fn calc_armor_dmg(
     dmg: f32,
     helmet: ItemKind,
     chestplate: ItemKind,
@@ -86,12 +197,116 @@ pub fn calc_dmg(
 }
 
 pub fn calc_dmg_with_weapon(
-    weapon: ItemKind,
-    helmet: ItemKind,
-    chestplate: ItemKind,
-    leggings: ItemKind,
-    boots: ItemKind,
+    weapon: &ItemStack,
+    critical: bool,
+    helmet: &ItemStack,
+    chestplate: &ItemStack,
+    leggings: &ItemStack,
+    boots: &ItemStack,
 ) -> f32 {
-    let dmg = item_dmg(weapon);
-    calc_dmg(dmg, helmet, chestplate, leggings, boots)
+    let dmg = weapon_dmg(weapon, critical);
+    calc_dmg(dmg, helmet, chestplate, leggings, boots, DamageType::Melee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use valence::nbt::{Compound, compound};
+
+    fn enchanted(item: ItemKind, enchantments: &[(&str, i16)]) -> ItemStack {
+        let list = enchantments
+            .iter()
+            .map(|(id, lvl)| {
+                compound! {
+                    "id" => format!("minecraft:{id}"),
+                    "lvl" => *lvl,
+                }
+            })
+            .collect::<Vec<Compound>>();
+        ItemStack::new(
+            item,
+            1,
+            Some(compound! { "Enchantments" => List::Compound(list) }),
+        )
+    }
+
+    #[test]
+    fn reads_enchantment_levels() {
+        let sword = enchanted(
+            ItemKind::DiamondSword,
+            &[("sharpness", 5), ("knockback", 2)],
+        );
+        assert_eq!(enchantment_level(&sword, "sharpness"), 5);
+        assert_eq!(enchantment_level(&sword, "knockback"), 2);
+        assert_eq!(enchantment_level(&sword, "fire_aspect"), 0);
+        assert_eq!(
+            enchantment_level(
+                &ItemStack::new(ItemKind::DiamondSword, 1, None),
+                "sharpness"
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn sharpness_and_crits() {
+        let plain = ItemStack::new(ItemKind::IronSword, 1, None);
+        assert_eq!(weapon_dmg(&plain, false), 6.0);
+        assert_eq!(weapon_dmg(&plain, true), 9.0);
+
+        let sharp = enchanted(ItemKind::DiamondSword, &[("sharpness", 5)]);
+        assert_eq!(weapon_dmg(&sharp, false), 13.25);
+        // Sharpness isn't multiplied by crits
+        assert_eq!(weapon_dmg(&sharp, true), 16.75);
+    }
+
+    #[test]
+    fn power() {
+        assert_eq!(arrow_base_dmg(0), 2.0);
+        assert_eq!(arrow_base_dmg(1), 3.0);
+        assert_eq!(arrow_base_dmg(5), 5.0);
+    }
+
+    #[test]
+    fn knockback_and_punch() {
+        assert_eq!(enchantment_knockback(0, DamageType::Melee), (0.0, 0.0));
+        assert_eq!(enchantment_knockback(2, DamageType::Melee), (20.0, 2.0));
+        assert_eq!(
+            enchantment_knockback(1, DamageType::Projectile),
+            (12.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn protection_epf_per_level() {
+        let epf = |level| {
+            piece_epf(
+                &enchanted(ItemKind::IronChestplate, &[("protection", level)]),
+                DamageType::Melee,
+            )
+        };
+        assert_eq!([epf(1), epf(2), epf(3), epf(4)], [1, 2, 3, 5]);
+
+        let projectile = enchanted(ItemKind::IronChestplate, &[("projectile_protection", 4)]);
+        assert_eq!(piece_epf(&projectile, DamageType::Melee), 0);
+        assert_eq!(piece_epf(&projectile, DamageType::Projectile), 11);
+    }
+
+    #[test]
+    fn protection_caps() {
+        let piece = enchanted(
+            ItemKind::DiamondChestplate,
+            &[("protection", 4), ("projectile_protection", 4)],
+        );
+        let armor = [&piece, &piece, &piece, &piece];
+        assert_eq!(protection_epf(armor, DamageType::Melee), 20);
+        assert_eq!(protection_epf(armor, DamageType::Projectile), 25);
+
+        // Full Protection IV reduces damage by 40% to 80%
+        assert_eq!(apply_epf(10.0, 20, 0), 6.0);
+        assert_eq!(apply_epf(10.0, 20, 10), 2.0);
+        // The reduction never goes past 80%
+        assert_eq!(apply_epf(10.0, 25, 12), 2.0);
+        assert_eq!(apply_epf(10.0, 0, 0), 10.0);
+    }
 }
@@ -3,25 +3,25 @@ pub mod models;
 pub mod schema;
 
 use diesel::prelude::*;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use valence::prelude::*;
 
 #[derive(Resource)]
-pub struct Database(pub Mutex<PgConnection>);
+pub struct Database(pub Arc<Mutex<PgConnection>>);
 
 pub struct DatabasePlugin {
-    connection_string: &'static str,
+    connection_string: String,
 }
 
 impl DatabasePlugin {
-    pub fn new(connection_string: &'static str) -> Self {
+    pub fn new(connection_string: String) -> Self {
         Self { connection_string }
     }
 }
 
 impl Plugin for DatabasePlugin {
     fn build(&self, app: &mut App) {
-        let db = PgConnection::establish(self.connection_string).unwrap();
-        app.insert_resource(Database(Mutex::new(db)));
+        let db = PgConnection::establish(&self.connection_string).unwrap();
+        app.insert_resource(Database(Arc::new(Mutex::new(db))));
     }
 }
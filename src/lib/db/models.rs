@@ -22,6 +22,8 @@ pub struct Player {
 pub struct Rank {
     pub id: i32,
     pub name: String,
+    pub prefix: String,
+    pub color: String,
 }
 
 #[derive(Queryable, Selectable, Debug)]
@@ -46,6 +48,7 @@ pub struct Friend {
 pub struct Guild {
     pub uuid: i32,
     pub name: String,
+    pub tag: String,
     pub experience_points: BigDecimal,
 }
 
@@ -60,6 +63,17 @@ pub struct MinigameStat {
     pub stat_value: BigDecimal,
 }
 
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = super::schema::ratings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Rating {
+    pub player_id: BigDecimal,
+    pub minigame: String,
+    pub season: i32,
+    pub rating: f64,
+    pub games: i32,
+}
+
 #[derive(Queryable, Selectable, Debug)]
 #[diesel(table_name = super::schema::minigame_inventories)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
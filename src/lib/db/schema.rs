@@ -18,6 +18,8 @@ table! {
     ranks (id) {
         id -> Int4,
         name -> Text,
+        prefix -> Text,
+        color -> Bpchar,
     }
 }
 
@@ -39,6 +41,7 @@ table! {
     guilds (uuid) {
         uuid -> Int4,
         name -> Text,
+        tag -> Text,
         experience_points -> Numeric,
     }
 }
@@ -53,6 +56,16 @@ table! {
     }
 }
 
+table! {
+    ratings (player_id, minigame, season) {
+        player_id -> Numeric,
+        minigame -> Text,
+        season -> Int4,
+        rating -> Float8,
+        games -> Int4,
+    }
+}
+
 table! {
     minigame_inventories (player_id, minigame) {
         player_id -> Numeric,
@@ -84,6 +97,7 @@ joinable!(rank_permissions -> ranks (rank_id));
 joinable!(friends -> players (player1));
 // joinable!(friends -> players (player2));
 joinable!(minigame_stats -> players (player_id));
+joinable!(ratings -> players (player_id));
 joinable!(minigame_inventories -> players (player_id));
 joinable!(player_achievements -> players (player_id));
 joinable!(player_achievements -> achievements (achievement_id));
@@ -95,6 +109,7 @@ allow_tables_to_appear_in_same_query!(
     friends,
     guilds,
     minigame_stats,
+    ratings,
     minigame_inventories,
     achievements,
     player_achievements,
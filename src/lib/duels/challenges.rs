@@ -0,0 +1,307 @@
+#![allow(clippy::type_complexity)]
+
+use super::{DuelsConfig, GameSettings, Match, ServerGlobals};
+use crate::network;
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+use valence::{
+    command::{AddCommand, handler::CommandResultEvent},
+    command_macros::Command,
+    prelude::*,
+};
+
+const EXPIRY_SECS: u64 = 60;
+
+// Kept outside of the app so the toggle follows players between subservers
+static BLOCKING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+#[derive(Command, Debug, Clone)]
+#[paths("duel")]
+#[scopes("minibit.commands.all.duel")]
+enum DuelCommand {
+    #[paths("accept {challenger?}")]
+    Accept { challenger: Option<String> },
+    #[paths("deny {challenger?}")]
+    Deny { challenger: Option<String> },
+    #[paths("toggle")]
+    Toggle,
+    #[paths("{target} {map?}")]
+    Challenge { target: String, map: Option<String> },
+}
+
+struct Challenge {
+    challenger: Entity,
+    target: Entity,
+    map: Option<usize>,
+    sent: Instant,
+}
+
+#[derive(Resource, Default)]
+struct Challenges(Vec<Challenge>);
+
+pub struct ChallengePlugin<T: Resource + DuelsConfig> {
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Resource + DuelsConfig + Sync + Send + 'static> Plugin for ChallengePlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_command::<DuelCommand>()
+            .init_resource::<Challenges>()
+            .add_systems(Update, (handle_duel_command::<T>, expire_challenges));
+    }
+}
+
+fn handle_duel_command<T: Resource + DuelsConfig>(
+    mut events: EventReader<CommandResultEvent<DuelCommand>>,
+    mut clients: Query<(Entity, &mut Client, &Username)>,
+    mut challenges: ResMut<Challenges>,
+    mut globals: ResMut<ServerGlobals>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    for event in events.read() {
+        match &event.result {
+            DuelCommand::Challenge { target, map } => {
+                let Some(
+                    [
+                        (_, mut client, username),
+                        (target, mut target_client, target_name),
+                    ],
+                ) = find_target(&mut clients, event.executor, target)
+                else {
+                    continue;
+                };
+                if settings.players_per_game() != 2 {
+                    client.send_chat_message(
+                        "Challenges are only available in 1v1 games!".color(Color::RED),
+                    );
+                    continue;
+                }
+                if !globals.queue.contains(&event.executor) {
+                    client.send_chat_message("You are already in a game!".color(Color::RED));
+                    continue;
+                }
+                if !globals.queue.contains(&target) {
+                    client.send_chat_message(
+                        (target_name.0.clone() + " is already in a game!").color(Color::RED),
+                    );
+                    continue;
+                }
+                if BLOCKING
+                    .lock()
+                    .unwrap()
+                    .contains(&target_name.0.to_lowercase())
+                {
+                    client.send_chat_message(
+                        (target_name.0.clone() + " is not accepting duel requests.")
+                            .color(Color::RED),
+                    );
+                    continue;
+                }
+
+                let map = match map {
+                    Some(name) => {
+                        // The first world is the waiting area
+                        let Some(index) = config
+                            .worlds()
+                            .iter()
+                            .skip(1)
+                            .position(|world| world.name().eq_ignore_ascii_case(name))
+                        else {
+                            let maps = config
+                                .worlds()
+                                .iter()
+                                .skip(1)
+                                .map(|world| world.name())
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            client.send_chat_message(
+                                (String::from("Unknown map! Available maps: ") + &maps)
+                                    .color(Color::RED),
+                            );
+                            continue;
+                        };
+                        Some(index + 1)
+                    }
+                    None => None,
+                };
+
+                challenges.0.retain(|challenge| {
+                    challenge.challenger != event.executor || challenge.target != target
+                });
+                challenges.0.push(Challenge {
+                    challenger: event.executor,
+                    target,
+                    map,
+                    sent: Instant::now(),
+                });
+
+                let map_name = map.map_or(String::new(), |index| {
+                    String::from(" on ") + config.worlds()[index].name()
+                });
+                client.send_chat_message(
+                    (String::from("Sent a duel request to ") + &target_name.0 + &map_name + ".")
+                        .color(Color::GREEN),
+                );
+                target_client.send_chat_message(
+                    Text::from(username.0.clone() + " challenged you to a duel" + &map_name + "! ")
+                        .color(Color::GOLD)
+                        + Text::from("[Accept]")
+                            .color(Color::GREEN)
+                            .on_click_run_command(String::from("/duel accept ") + &username.0)
+                        + Text::from(" ")
+                        + Text::from("[Deny]")
+                            .color(Color::RED)
+                            .on_click_run_command(String::from("/duel deny ") + &username.0),
+                );
+            }
+            DuelCommand::Accept { challenger } | DuelCommand::Deny { challenger } => {
+                let accept = matches!(event.result, DuelCommand::Accept { .. });
+                let challenger = challenger.as_ref().map(|name| {
+                    clients
+                        .iter()
+                        .find(|(_, _, username)| username.0.eq_ignore_ascii_case(name))
+                        .map(|(entity, ..)| entity)
+                });
+                let Some(index) = challenges.0.iter().rposition(|challenge| {
+                    challenge.target == event.executor
+                        && challenger.is_none_or(|entity| entity == Some(challenge.challenger))
+                }) else {
+                    if let Ok((_, mut client, ..)) = clients.get_mut(event.executor) {
+                        client.send_chat_message(
+                            "You don't have a pending duel request!".color(Color::RED),
+                        );
+                    }
+                    continue;
+                };
+                let challenge = challenges.0.remove(index);
+
+                let Ok(
+                    [
+                        (_, mut client, username),
+                        (_, mut challenger_client, challenger_name),
+                    ],
+                ) = clients.get_many_mut([event.executor, challenge.challenger])
+                else {
+                    continue;
+                };
+                if !accept {
+                    client.send_chat_message(
+                        (String::from("Denied the duel request from ") + &challenger_name.0 + ".")
+                            .color(Color::GRAY),
+                    );
+                    challenger_client.send_chat_message(
+                        (username.0.clone() + " denied your duel request.").color(Color::RED),
+                    );
+                    continue;
+                }
+                if !globals.queue.contains(&event.executor) {
+                    client.send_chat_message("You are already in a game!".color(Color::RED));
+                    continue;
+                }
+                if !globals.queue.contains(&challenge.challenger) {
+                    client.send_chat_message(
+                        (challenger_name.0.clone() + " is already in a game!").color(Color::RED),
+                    );
+                    continue;
+                }
+
+                globals
+                    .queue
+                    .retain(|&entity| entity != event.executor && entity != challenge.challenger);
+                globals.matches.push(Match {
+                    players: vec![challenge.challenger, event.executor],
+                    map: challenge.map,
+                    ranked: false,
+                });
+                // Other requests involving either player can't be accepted anymore
+                challenges.0.retain(|other| {
+                    ![other.challenger, other.target]
+                        .iter()
+                        .any(|entity| *entity == event.executor || *entity == challenge.challenger)
+                });
+                challenger_client.send_chat_message(
+                    (username.0.clone() + " accepted your duel request!").color(Color::GREEN),
+                );
+            }
+            DuelCommand::Toggle => {
+                let Ok((_, mut client, username)) = clients.get_mut(event.executor) else {
+                    continue;
+                };
+                let mut blocking = BLOCKING.lock().unwrap();
+                let name = username.0.to_lowercase();
+                if blocking.remove(&name) {
+                    client.send_chat_message(
+                        "You are now accepting duel requests.".color(Color::GREEN),
+                    );
+                } else {
+                    blocking.insert(name);
+                    challenges
+                        .0
+                        .retain(|challenge| challenge.target != event.executor);
+                    client.send_chat_message(
+                        "You are no longer accepting duel requests.".color(Color::RED),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Looks up the executor and a player on this subserver, telling the executor if they can't be
+/// challenged here.
+fn find_target<'a>(
+    clients: &'a mut Query<(Entity, &mut Client, &Username)>,
+    executor: Entity,
+    name: &str,
+) -> Option<[(Entity, Mut<'a, Client>, &'a Username); 2]> {
+    let target = clients
+        .iter()
+        .find(|(_, _, username)| username.0.eq_ignore_ascii_case(name))
+        .map(|(entity, ..)| entity);
+    let target = match target {
+        Some(target) if target != executor => target,
+        Some(_) => {
+            if let Ok((_, mut client, ..)) = clients.get_mut(executor) {
+                client.send_chat_message("You can't duel yourself!".color(Color::RED));
+            }
+            return None;
+        }
+        None => {
+            let message = match network::find_player(name) {
+                Some(player) => {
+                    player.username + " is playing on " + &player.server + ", not this game!"
+                }
+                None => String::from("Player ") + name + " is not online!",
+            };
+            if let Ok((_, mut client, ..)) = clients.get_mut(executor) {
+                client.send_chat_message(message.color(Color::RED));
+            }
+            return None;
+        }
+    };
+    clients.get_many_mut([executor, target]).ok()
+}
+
+fn expire_challenges(
+    mut clients: Query<(&mut Client, &Username)>,
+    mut challenges: ResMut<Challenges>,
+) {
+    challenges.0.retain(|challenge| {
+        // Challenges from or to players who left are dropped
+        let Ok([(mut client, _), (_, target)]) =
+            clients.get_many_mut([challenge.challenger, challenge.target])
+        else {
+            return false;
+        };
+        if challenge.sent.elapsed().as_secs() < EXPIRY_SECS {
+            return true;
+        }
+        client.send_chat_message(
+            (String::from("Your duel request to ") + &target.0 + " expired.").color(Color::GRAY),
+        );
+        false
+    });
+}
@@ -3,6 +3,7 @@
 
 use super::*;
 use crate::config::DataPath;
+use std::time::Instant;
 use valence::layer::UpdateLayersPreClientSet;
 use valence::prelude::*;
 use valence_anvil::AnvilLevel;
@@ -14,7 +15,8 @@ pub struct Game {
     pub clients: Entities,
     pub game_start: GameTime,
     pub game_stage: GameStage,
-    pub data: GameData,
+    pub round_start: RoundTime,
+    pub scores: TeamScores,
 }
 
 #[derive(Resource)]
@@ -32,7 +34,11 @@ impl<T: Resource + DeserializeOwned + DuelsConfig + Sync + Send + 'static> Plugi
             .add_systems(Update, (init_clients::<T>,))
             .add_systems(
                 PostUpdate,
-                (check_queue::<T>, end_game::<T>).before(UpdateLayersPreClientSet),
+                (
+                    check_queue::<T>.after(MatchmakingSet).in_set(SpawnGameSet),
+                    end_game::<T>.in_set(EndGameSet),
+                )
+                    .before(UpdateLayersPreClientSet),
             );
     }
 }
@@ -148,16 +154,14 @@ pub fn check_queue<T: Resource + DuelsConfig>(
     dimensions: Res<DimensionTypeRegistry>,
     biomes: Res<BiomeRegistry>,
     data_path: Res<DataPath>,
+    settings: Res<GameSettings>,
     mut commands: Commands,
     mut server_globals: ResMut<ServerGlobals>,
+    mut rotation: ResMut<MapRotation>,
 ) {
-    if server_globals.queue.len() < 2 {
-        return;
-    }
-    fastrand::shuffle(&mut server_globals.queue);
-    while server_globals.queue.len() > 1 {
+    for game in std::mem::take(&mut server_globals.matches) {
         start_game(
-            server_globals.queue.drain(..2).collect(),
+            game,
             &mut clients,
             &mut start_game_ev,
             &mut commands,
@@ -167,12 +171,14 @@ pub fn check_queue<T: Resource + DuelsConfig>(
             &globals,
             &config,
             &data_path,
+            &settings,
+            &mut rotation,
         );
     }
 }
 
 fn start_game<T: Resource + DuelsConfig>(
-    entities: Vec<Entity>,
+    game: Match,
     clients: &mut Query<(
         &mut Client,
         &mut PlayerGameState,
@@ -191,8 +197,11 @@ fn start_game<T: Resource + DuelsConfig>(
     globals: &Res<MapGlobals>,
     config: &Res<T>,
     data_path: &Res<DataPath>,
+    settings: &Res<GameSettings>,
+    rotation: &mut ResMut<MapRotation>,
 ) {
-    let map_idx = fastrand::usize(1..config.worlds().len());
+    let map_idx = game.map.unwrap_or_else(|| rotation.pick(config.worlds()));
+    rotation.last = Some(map_idx);
     let world = &config.worlds()[map_idx];
     let layer = commands
         .spawn(init_world(world, server, dimensions, biomes, data_path))
@@ -202,14 +211,18 @@ fn start_game<T: Resource + DuelsConfig>(
         .spawn(Game {
             map_index: MapIndex(map_idx),
             layer: EntityLayerId(layer),
-            clients: Entities(entities.clone()),
+            clients: Entities(game.players.clone()),
             game_start: GameTime(SystemTime::now()),
             game_stage: GameStage(0),
-            data: GameData(HashMap::new()),
+            round_start: RoundTime(Instant::now()),
+            scores: TeamScores::new(settings.team_count),
         })
         .id();
+    if !game.ranked {
+        commands.entity(game_id).insert(Unranked);
+    }
 
-    for (i, entity) in entities.iter().enumerate() {
+    for (i, entity) in game.players.iter().enumerate() {
         let Ok((
             mut client,
             mut gamestate,
@@ -230,9 +243,10 @@ fn start_game<T: Resource + DuelsConfig>(
         visible_entity_layers.0.insert(layer);
 
         gamestate.game_id = Some(game_id);
-        gamestate.team = i as u8 % 2;
+        gamestate.team = (i % settings.team_count) as u8;
+        gamestate.slot = (i / settings.team_count) as u8;
 
-        let spawn = &world.spawns[gamestate.team as usize % 2];
+        let spawn = world.spawn(gamestate.team, gamestate.slot, settings.team_size);
         pos.set(spawn.pos);
         look.yaw = spawn.rot[0];
         look.pitch = spawn.rot[1];
@@ -285,15 +299,14 @@ pub fn end_game<T: Resource + DuelsConfig>(
             pos.set(config.worlds()[0].spawns[0].pos);
             health.0 = 20.0;
 
-            if gamestate.team == event.loser {
-                client.send_chat_message("You lost!");
-            } else {
-                client.send_chat_message("You won!");
+            client.send_chat_message(event.result.message(gamestate.team));
+            if event.result.is_winner(gamestate.team) {
                 gamestate.wins += 1;
             }
 
             gamestate.game_id = None;
             gamestate.team = 0;
+            gamestate.slot = 0;
 
             server_globals.queue.push(*entity);
         }
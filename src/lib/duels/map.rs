@@ -1,7 +1,9 @@
 #![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
 
 use super::*;
 use crate::config::DataPath;
+use std::time::Instant;
 use valence::prelude::*;
 use valence_anvil::AnvilLevel;
 
@@ -12,7 +14,8 @@ pub struct Game {
     pub clients: Entities,
     pub game_start: GameTime,
     pub game_stage: GameStage,
-    pub data: GameData,
+    pub round_start: RoundTime,
+    pub scores: TeamScores,
 }
 
 #[derive(Resource)]
@@ -31,7 +34,13 @@ impl<T: Resource + DeserializeOwned + DuelsConfig + Sync + Send + 'static> Plugi
                 Update,
                 (init_clients::<T>, start_game::<T>.after(init_clients::<T>)),
             )
-            .add_systems(PostUpdate, (check_queue, end_game::<T>));
+            .add_systems(
+                PostUpdate,
+                (
+                    check_queue::<T>.after(MatchmakingSet).in_set(SpawnGameSet),
+                    end_game::<T>.in_set(EndGameSet),
+                ),
+            );
     }
 }
 
@@ -111,29 +120,34 @@ pub fn init_clients<T: Resource + DuelsConfig>(
     }
 }
 
-pub fn check_queue(
+pub fn check_queue<T: Resource + DuelsConfig>(
     mut start_game: EventWriter<StartGameEvent>,
     server: Res<Server>,
     mut commands: Commands,
     mut globals: ResMut<ServerGlobals>,
+    mut rotation: ResMut<MapRotation>,
+    config: Res<T>,
+    settings: Res<GameSettings>,
 ) {
-    if globals.queue.len() < 2 {
-        return;
-    }
-    fastrand::shuffle(&mut globals.queue);
-    while globals.queue.len() > 1 {
+    for game in std::mem::take(&mut globals.matches) {
         let entitylayer = commands.spawn(EntityLayer::new(&server)).id();
+        let map_idx = game.map.unwrap_or_else(|| rotation.pick(config.worlds()));
+        rotation.last = Some(map_idx);
 
         let game_id = commands
             .spawn(Game {
-                map: MapIndex(0),
+                map: MapIndex(map_idx),
                 layer: EntityLayerId(entitylayer),
-                clients: Entities(globals.queue.drain(..2).collect()),
+                clients: Entities(game.players),
                 game_start: GameTime(SystemTime::now()),
                 game_stage: GameStage(0),
-                data: GameData(HashMap::new()),
+                round_start: RoundTime(Instant::now()),
+                scores: TeamScores::new(settings.team_count),
             })
             .id();
+        if !game.ranked {
+            commands.entity(game_id).insert(Unranked);
+        }
 
         start_game.send(StartGameEvent(game_id));
     }
@@ -150,17 +164,17 @@ pub fn start_game<T: Resource + DuelsConfig>(
         &mut Look,
         &mut HeadYaw,
     )>,
-    mut games: Query<(&mut MapIndex, &EntityLayerId, &Entities), Without<Client>>,
+    games: Query<(&MapIndex, &EntityLayerId, &Entities), Without<Client>>,
     chunklayers: Query<Entity, With<ChunkLayer>>,
     entitylayers: Query<Entity, With<EntityLayer>>,
     mut start_game: EventReader<StartGameEvent>,
     globals: Res<MapGlobals>,
+    settings: Res<GameSettings>,
     config: Res<T>,
 ) {
     for event in start_game.read() {
-        if let Ok((mut map, game_layer, entities)) = games.get_mut(event.0) {
-            let map_idx = fastrand::usize(1..globals.map_layers.len());
-            map.0 = map_idx;
+        if let Ok((map, game_layer, entities)) = games.get(event.0) {
+            let map_idx = map.0;
 
             for (i, entity) in entities.0.iter().enumerate() {
                 let Ok((
@@ -189,9 +203,14 @@ pub fn start_game<T: Resource + DuelsConfig>(
                 visible_entity_layers.0.insert(entitylayer);
 
                 gamestate.game_id = Some(event.0);
-                gamestate.team = i as u8 % 2;
+                gamestate.team = (i % settings.team_count) as u8;
+                gamestate.slot = (i / settings.team_count) as u8;
 
-                let spawn = &config.worlds()[map_idx].spawns[gamestate.team as usize % 2];
+                let spawn = config.worlds()[map_idx].spawn(
+                    gamestate.team,
+                    gamestate.slot,
+                    settings.team_size,
+                );
                 pos.set(spawn.pos);
                 look.yaw = spawn.rot[0];
                 look.pitch = spawn.rot[1];
@@ -244,15 +263,14 @@ pub fn end_game<T: Resource + DuelsConfig>(
             pos.set(config.worlds()[0].spawns[0].pos);
             health.0 = 20.0;
 
-            if gamestate.team == event.loser {
-                client.send_chat_message("You lost!");
-            } else {
-                client.send_chat_message("You won!");
+            client.send_chat_message(event.result.message(gamestate.team));
+            if event.result.is_winner(gamestate.team) {
                 gamestate.wins += 1;
             }
 
             gamestate.game_id = None;
             gamestate.team = 0;
+            gamestate.slot = 0;
 
             server_globals.queue.push(*entity);
         }
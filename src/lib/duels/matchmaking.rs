@@ -0,0 +1,145 @@
+use super::ranked::{RankedSettings, Rating};
+use super::{GameSettings, Match, ServerGlobals};
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+use valence::prelude::*;
+
+#[derive(Resource, Default)]
+pub struct QueueTimes(pub HashMap<Entity, Instant>);
+
+/// How many matched players' waits the queue estimate is based on.
+const RECENT_WAITS: usize = 20;
+
+/// Seconds that recently matched players spent in the queue.
+#[derive(Resource, Default)]
+pub struct RecentWaits(pub VecDeque<f64>);
+
+impl RecentWaits {
+    pub fn average(&self) -> Option<f64> {
+        (!self.0.is_empty()).then(|| self.0.iter().sum::<f64>() / self.0.len() as f64)
+    }
+}
+
+pub fn track_queue_times(globals: Res<ServerGlobals>, mut times: ResMut<QueueTimes>) {
+    times.0.retain(|entity, _| globals.queue.contains(entity));
+    for entity in globals.queue.iter() {
+        times.0.entry(*entity).or_insert_with(Instant::now);
+    }
+}
+
+/// Remembers how long the players of new matches waited. Must run before the matches are taken
+/// out of `ServerGlobals::matches`, while the players still have a queue time.
+pub fn record_waits(
+    globals: Res<ServerGlobals>,
+    times: Res<QueueTimes>,
+    mut waits: ResMut<RecentWaits>,
+) {
+    for game in globals.matches.iter() {
+        for entity in game.players.iter() {
+            let Some(time) = times.0.get(entity) else {
+                continue;
+            };
+            waits.0.push_back(time.elapsed().as_secs_f64());
+            if waits.0.len() > RECENT_WAITS {
+                waits.0.pop_front();
+            }
+        }
+    }
+}
+
+/// Moves players from the queue into `ServerGlobals::matches`. Casual queues are matched randomly,
+/// ranked queues only match players within a rating window that widens the longer they wait.
+pub fn form_matches(
+    mut globals: ResMut<ServerGlobals>,
+    ratings: Query<&Rating>,
+    times: Res<QueueTimes>,
+    settings: Res<GameSettings>,
+    ranked: Res<RankedSettings>,
+) {
+    let players = settings.players_per_game();
+    if globals.queue.len() < players {
+        return;
+    }
+    if !ranked.enabled {
+        fastrand::shuffle(&mut globals.queue);
+        while globals.queue.len() >= players {
+            let players = globals.queue.drain(..players).collect();
+            globals.matches.push(Match {
+                players,
+                map: None,
+                ranked: false,
+            });
+        }
+        return;
+    }
+
+    let rating = |entity: Entity| {
+        ratings
+            .get(entity)
+            .map_or(ranked.default_rating, |rating| rating.rating)
+    };
+    let waited = |entity: Entity| {
+        times
+            .0
+            .get(&entity)
+            .map_or(0.0, |time| time.elapsed().as_secs_f64())
+    };
+
+    // Players who waited the longest get matched first
+    let mut waiting = globals.queue.clone();
+    waiting.sort_by(|a, b| waited(*b).total_cmp(&waited(*a)));
+
+    let mut matched = Vec::new();
+    for &anchor in waiting.iter() {
+        if matched.contains(&anchor) {
+            continue;
+        }
+        let anchor_rating = rating(anchor);
+        let window = ranked.window(waited(anchor));
+        let mut candidates: Vec<Entity> = waiting
+            .iter()
+            .copied()
+            .filter(|entity| {
+                !matched.contains(entity) && (rating(*entity) - anchor_rating).abs() <= window
+            })
+            .collect();
+        if candidates.len() < players {
+            continue;
+        }
+        candidates.sort_by(|a, b| {
+            (rating(*a) - anchor_rating)
+                .abs()
+                .total_cmp(&(rating(*b) - anchor_rating).abs())
+        });
+        candidates.truncate(players);
+        matched.extend(candidates.iter().copied());
+        globals.matches.push(Match {
+            players: balance_teams(candidates, rating, settings.team_count),
+            map: None,
+            ranked: true,
+        });
+    }
+    globals.queue.retain(|entity| !matched.contains(entity));
+}
+
+/// Orders players so that the teams given out by index (`i % team_count`) have similar ratings,
+/// picking in a snake draft from the highest rated player down.
+fn balance_teams(
+    mut entities: Vec<Entity>,
+    rating: impl Fn(Entity) -> f64,
+    team_count: usize,
+) -> Vec<Entity> {
+    entities.sort_by(|a, b| rating(*b).total_cmp(&rating(*a)));
+    let mut ordered = entities.clone();
+    for (i, entity) in entities.into_iter().enumerate() {
+        let round = i / team_count;
+        let pick = i % team_count;
+        let team = if round % 2 == 0 {
+            pick
+        } else {
+            team_count - 1 - pick
+        };
+        ordered[round * team_count + team] = entity;
+    }
+    ordered
+}
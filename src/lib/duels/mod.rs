@@ -1,21 +1,54 @@
 #![allow(dead_code)]
 
+pub mod challenges;
 pub mod copied_map;
 pub mod map;
+pub mod matchmaking;
 pub mod oob;
+pub mod queue;
+pub mod ranked;
+pub mod reconnect;
+pub mod rematch;
+pub mod spectate;
+pub mod stages;
+pub mod time_limit;
+pub mod voting;
 
 use bevy_ecs::query::QueryData;
 use serde::{Deserialize, de::DeserializeOwned};
 use std::path::PathBuf;
-use std::{collections::HashMap, marker::PhantomData, time::SystemTime};
+use std::{collections::VecDeque, marker::PhantomData, time::SystemTime};
 use valence::{
+    command::{AddCommand, handler::CommandResultEvent, parsers::GreedyString},
+    command_macros::Command,
     entity::living::Health,
-    message::ChatMessageEvent,
     prelude::*,
     protocol::{Sound, sound::SoundCategory},
 };
 
+use super::anticheat::{AntiCheatPlugin, combat::reach_distance};
+use super::chat::{ChatEvent, ChatFormat, ChatInputEvent, ChatPlugin, ChatSet};
+use super::color::format;
 use super::config::{ConfigLoaderPlugin, NetworkConfig, WorldValue};
+use super::kits::KitPlugin;
+use super::messaging::MessagingPlugin;
+use super::network::NetworkPlugin;
+use super::profile::{PlayerProfile, ProfilePlugin};
+use super::reports::ReportPlugin;
+use super::scopes::ScopePlugin;
+use challenges::ChallengePlugin;
+use matchmaking::{QueueTimes, RecentWaits, form_matches, record_waits, track_queue_times};
+use queue::QueuePlugin;
+use ranked::{RankedPlugin, Unranked};
+use reconnect::{ReconnectPlugin, reconnect};
+use rematch::RematchPlugin;
+use spectate::{SpectatePlugin, Spectating};
+use stages::{RoundTime, StagePlugin, StageSchedule};
+use time_limit::{TeamScores, TimeLimitPlugin};
+use voting::{MapRotation, MapVotePlugin};
+
+/// Messages starting with this go to everyone in the game instead of just the sender's team.
+pub const SHOUT_PREFIX: &str = "!";
 
 #[derive(Component)]
 pub struct MapIndex(pub usize);
@@ -29,36 +62,117 @@ pub struct GameTime(pub SystemTime);
 #[derive(Component)]
 pub struct GameStage(pub u8);
 
-pub enum DataValue {
-    Int(i32),
-    Float(f32),
-    String(String),
+/// Adds a minigame's own per-game component `S` to every game when it is spawned. It is in
+/// place by the time `StartGameEvent` is handled.
+pub struct GameStatePlugin<S: Component + Default> {
+    pub phantom: PhantomData<S>,
 }
 
-#[derive(Component)]
-pub struct GameData(pub HashMap<usize, DataValue>);
+impl<S: Component + Default> Plugin for GameStatePlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, init_game_state::<S>.after(SpawnGameSet));
+    }
+}
+
+fn init_game_state<S: Component + Default>(
+    games: Query<Entity, Added<Entities>>,
+    mut commands: Commands,
+) {
+    for game_id in games.iter() {
+        commands.entity(game_id).insert(S::default());
+    }
+}
 
 #[derive(Component, Default)]
 pub struct PlayerGameState {
     pub game_id: Option<Entity>,
     pub team: u8,
+    pub slot: u8,
     pub wins: u32,
 }
 
+/// How many of a player's most recent hits are kept for reports.
+pub const HIT_HISTORY: usize = 20;
+
+#[derive(Clone)]
+pub struct HitRecord {
+    pub tick: i64,
+    pub victim: String,
+    pub reach: f64,
+    pub attacker_pos: DVec3,
+    pub victim_pos: DVec3,
+}
+
 #[derive(Component, Default)]
 pub struct CombatState {
     pub last_attacked_tick: i64,
     pub has_bonus_knockback: bool,
     pub last_attacker: Option<Entity>,
+    pub hits: VecDeque<HitRecord>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChatChannel {
+    All,
+    Team,
+    Spectators,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("shout {message}")]
+#[scopes("minibit.commands.all.shout")]
+struct ShoutCommand {
+    message: GreedyString,
 }
 
 #[derive(Event)]
 pub struct StartGameEvent(pub Entity);
 
+#[derive(Clone)]
+pub enum GameResult {
+    /// One team lost and every other team won.
+    Loser(u8),
+    /// Teams from first to last place. Only the first team wins, and teams missing from the
+    /// ranking lost.
+    Ranking(Vec<u8>),
+    /// Nobody won.
+    Draw,
+}
+
+impl GameResult {
+    pub fn is_winner(&self, team: u8) -> bool {
+        match self {
+            GameResult::Loser(loser) => team != *loser,
+            GameResult::Ranking(ranking) => ranking.first() == Some(&team),
+            GameResult::Draw => false,
+        }
+    }
+
+    /// 1-based place of the team, if the result ranks teams.
+    pub fn placement(&self, team: u8) -> Option<usize> {
+        match self {
+            GameResult::Loser(_) | GameResult::Draw => None,
+            GameResult::Ranking(ranking) => ranking.iter().position(|&t| t == team).map(|i| i + 1),
+        }
+    }
+
+    pub fn message(&self, team: u8) -> String {
+        if matches!(self, GameResult::Draw) {
+            "It's a draw!".to_string()
+        } else if self.is_winner(team) {
+            "You won!".to_string()
+        } else if let Some(place) = self.placement(team) {
+            format!("You placed #{}!", place)
+        } else {
+            "You lost!".to_string()
+        }
+    }
+}
+
 #[derive(Event)]
 pub struct EndGameEvent {
     pub game_id: Entity,
-    pub loser: u8,
+    pub result: GameResult,
 }
 
 #[derive(Event)]
@@ -67,14 +181,45 @@ pub struct GameStageEvent {
     pub stage: u8,
 }
 
+pub struct Match {
+    /// Players in team order.
+    pub players: Vec<Entity>,
+    /// World index to play on, or a random map if `None`.
+    pub map: Option<usize>,
+    pub ranked: bool,
+}
+
 #[derive(Resource)]
 pub struct ServerGlobals {
     pub queue: Vec<Entity>,
+    /// Matches waiting for their game to be created.
+    pub matches: Vec<Match>,
 }
 
+/// Picks players from the queue for new games.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MatchmakingSet;
+
+/// Spawns games for the matches that were made.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpawnGameSet;
+
+/// Handles `EndGameEvent`s and tears the games down. Systems that need the finished game's teams
+/// should run before this set.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EndGameSet;
+
 #[derive(Resource)]
 pub struct GameSettings {
     pub default_gamemode: GameMode,
+    pub team_size: usize,
+    pub team_count: usize,
+}
+
+impl GameSettings {
+    pub fn players_per_game(&self) -> usize {
+        self.team_size * self.team_count
+    }
 }
 
 pub trait DuelsConfig {
@@ -96,6 +241,8 @@ pub struct DuelsPlugin<T: DeserializeOwned + DuelsConfig> {
     pub path: PathBuf,
     pub network_config: NetworkConfig,
     pub default_gamemode: GameMode,
+    pub team_size: usize,
+    pub team_count: usize,
     pub copy_map: bool,
     pub phantom: PhantomData<T>,
 }
@@ -109,62 +256,164 @@ impl<T: Resource + DeserializeOwned + DuelsConfig + Sync + Send + 'static> Plugi
             network_config: self.network_config.clone(),
             phantom: PhantomData,
         })
+        .add_plugins((
+            AntiCheatPlugin,
+            ScopePlugin,
+            ProfilePlugin,
+            ChatPlugin,
+            KitPlugin,
+            NetworkPlugin,
+            MessagingPlugin,
+            ReportPlugin,
+            QueuePlugin,
+            RankedPlugin,
+            ReconnectPlugin,
+            SpectatePlugin,
+            StagePlugin,
+            TimeLimitPlugin,
+        ))
+        .add_plugins((
+            ChallengePlugin::<T> {
+                phantom: PhantomData,
+            },
+            MapVotePlugin::<T> {
+                phantom: PhantomData,
+            },
+            RematchPlugin::<T> {
+                phantom: PhantomData,
+            },
+        ))
         .insert_resource(GameSettings {
             default_gamemode: self.default_gamemode,
+            team_size: self.team_size,
+            team_count: self.team_count,
+        })
+        .insert_resource(ServerGlobals {
+            queue: Vec::new(),
+            matches: Vec::new(),
         })
-        .insert_resource(ServerGlobals { queue: Vec::new() })
+        .init_resource::<QueueTimes>()
+        .init_resource::<RecentWaits>()
         .add_event::<StartGameEvent>()
         .add_event::<EndGameEvent>()
         .add_event::<GameStageEvent>()
+        .add_command::<ShoutCommand>()
         .add_systems(
             Update,
             (
-                despawn_disconnected_clients,
                 start_game,
                 gamestage_change.after(gameloop::<T>),
-                chat_message,
+                chat_message.after(ChatSet),
+                handle_shout_command.before(ChatSet),
             ),
         )
-        .add_systems(PostUpdate, handle_disconnect);
+        .add_systems(EventLoopUpdate, record_hits)
+        .add_systems(
+            PostUpdate,
+            (
+                handle_disconnect.before(EndGameSet),
+                (track_queue_times, form_matches, record_waits)
+                    .chain()
+                    .in_set(MatchmakingSet),
+            ),
+        );
 
         if self.copy_map {
             app.add_plugins(copied_map::MapPlugin::<T> {
                 phantom: PhantomData,
             })
-            .add_systems(Update, gameloop::<T>);
+            .add_systems(
+                Update,
+                (
+                    gameloop::<T>,
+                    reconnect.after(copied_map::init_clients::<T>),
+                ),
+            );
         } else {
             app.add_plugins(map::MapPlugin::<T> {
                 phantom: PhantomData,
             })
-            .add_systems(Update, gameloop::<T>);
+            .add_systems(
+                Update,
+                (gameloop::<T>, reconnect.after(map::init_clients::<T>)),
+            );
         }
     }
 }
 
 pub fn handle_disconnect(
-    disconncted: Query<(Entity, &PlayerGameState), Added<Despawned>>,
-    mut clients: Query<(&mut Client, &PlayerGameState)>,
+    disconncted: Query<(Entity, &PlayerGameState, &Username), Added<Despawned>>,
+    mut clients: Query<(&mut Client, &PlayerGameState), Without<Despawned>>,
     mut end_game: EventWriter<EndGameEvent>,
     mut globals: ResMut<ServerGlobals>,
 ) {
-    for (entity, dc_gamestate) in disconncted.iter() {
+    for (entity, dc_gamestate, username) in disconncted.iter() {
         if globals.queue.contains(&entity) {
             globals.queue.retain(|&x| x != entity);
-        } else {
-            for (mut client, gamestate) in clients.iter_mut() {
-                if gamestate.game_id == dc_gamestate.game_id {
-                    client.send_chat_message("Your opponent disconnected!");
-                    client.clear_title();
-                }
+            continue;
+        }
+        let Some(game_id) = dc_gamestate.game_id else {
+            continue;
+        };
+        let teammates_left = clients.iter().any(|(_, gamestate)| {
+            gamestate.game_id == dc_gamestate.game_id && gamestate.team == dc_gamestate.team
+        });
+        for (mut client, gamestate) in clients.iter_mut() {
+            if gamestate.game_id != dc_gamestate.game_id {
+                continue;
             }
+            if teammates_left {
+                client.send_chat_message(username.0.clone() + " disconnected!");
+            } else {
+                client.send_chat_message("Your opponent disconnected!");
+                client.clear_title();
+            }
+        }
+        if !teammates_left {
             end_game.send(EndGameEvent {
-                game_id: dc_gamestate.game_id.unwrap(),
-                loser: dc_gamestate.team,
+                game_id,
+                result: GameResult::Loser(dc_gamestate.team),
             });
         }
     }
 }
 
+pub fn record_hits(
+    mut attackers: Query<(&Position, &PlayerGameState, &mut CombatState)>,
+    victims: Query<(&Position, &PlayerGameState, &Username)>,
+    mut interact_entity: EventReader<InteractEntityEvent>,
+    server: Res<Server>,
+) {
+    for event in interact_entity.read() {
+        if event.interact != EntityInteraction::Attack {
+            continue;
+        }
+        let (
+            Ok((attacker_pos, attacker_gamestate, mut state)),
+            Ok((victim_pos, victim_gamestate, victim)),
+        ) = (attackers.get_mut(event.client), victims.get(event.entity))
+        else {
+            continue;
+        };
+        if attacker_gamestate.game_id.is_none()
+            || attacker_gamestate.game_id != victim_gamestate.game_id
+        {
+            continue;
+        }
+
+        if state.hits.len() >= HIT_HISTORY {
+            state.hits.pop_front();
+        }
+        state.hits.push_back(HitRecord {
+            tick: server.current_tick(),
+            victim: victim.0.clone(),
+            reach: reach_distance(attacker_pos.0, victim_pos.0),
+            attacker_pos: attacker_pos.0,
+            victim_pos: victim_pos.0,
+        });
+    }
+}
+
 pub fn start_game(
     mut clients: Query<&mut CombatState, With<Client>>,
     games: Query<&Entities>,
@@ -175,7 +424,12 @@ pub fn start_game(
         if let Ok(entities) = games.get(event.0) {
             for entity in entities.0.iter() {
                 if let Ok(mut state) = clients.get_mut(*entity) {
-                    *state = CombatState::default();
+                    // Hits from the previous game are kept as evidence for reports
+                    let hits = std::mem::take(&mut state.hits);
+                    *state = CombatState {
+                        hits,
+                        ..Default::default()
+                    };
                 }
             }
         }
@@ -198,15 +452,21 @@ pub struct GameQuery {
 
 pub fn gameloop<T: Resource + DuelsConfig>(
     mut clients: Query<GameQuery>,
-    mut games: Query<(Entity, &Entities, &MapIndex, &mut GameStage, &GameTime)>,
+    mut games: Query<(Entity, &Entities, &MapIndex, &mut GameStage, &RoundTime)>,
     mut gamestage: EventWriter<GameStageEvent>,
+    schedule: Res<StageSchedule>,
     config: Res<T>,
+    settings: Res<GameSettings>,
 ) {
-    for (game_id, entities, map, mut stage, time) in games.iter_mut() {
-        if stage.0 < 4 {
+    for (game_id, entities, map, mut stage, round) in games.iter_mut() {
+        if schedule.freezes(stage.0) {
             for entity in entities.0.iter() {
                 if let Ok(mut player) = clients.get_mut(*entity) {
-                    let spawn = &config.worlds()[map.0].spawns[player.gamestate.team as usize % 2];
+                    let spawn = config.worlds()[map.0].spawn(
+                        player.gamestate.team,
+                        player.gamestate.slot,
+                        settings.team_size,
+                    );
                     player.pos.set(spawn.pos);
                     player.look.yaw = spawn.rot[0];
                     player.look.pitch = spawn.rot[1];
@@ -214,7 +474,12 @@ pub fn gameloop<T: Resource + DuelsConfig>(
                 }
             }
         }
-        if (stage.0 < 5 && time.0.elapsed().unwrap().as_secs() >= stage.0 as u64) || stage.0 == 0 {
+        // Stage ends are measured from the start of the round, so a slow tick doesn't delay the
+        // stages after it
+        if schedule
+            .end_of(stage.0)
+            .is_some_and(|end| round.0.elapsed().as_secs_f64() >= end)
+        {
             stage.0 += 1;
             gamestage.send(GameStageEvent {
                 game_id,
@@ -228,34 +493,27 @@ pub fn gamestage_change(
     mut clients: Query<(&mut Client, &Position)>,
     games: Query<&Entities>,
     mut gamestage: EventReader<GameStageEvent>,
+    schedule: Res<StageSchedule>,
 ) {
     for event in gamestage.read() {
+        let Some(stage) = schedule.0.get(event.stage as usize) else {
+            continue;
+        };
         if let Ok(entities) = games.get(event.game_id) {
             for entity in entities.0.iter() {
                 if let Ok((mut client, pos)) = clients.get_mut(*entity) {
-                    match event.stage {
-                        1 => client.set_title("3".color(Color::GREEN)),
-                        2 => client.set_title("2".color(Color::GOLD)),
-                        3 => client.set_title("1".color(Color::RED)),
-                        4 => client.set_title("GO!".color(Color::RED)),
-                        5 => client.clear_title(),
-                        _ => {}
+                    if let Some(title) = &stage.title {
+                        client.set_title(title.clone());
+                    } else if stage.clear_title {
+                        client.clear_title();
                     }
-                    if event.stage < 4 {
-                        client.play_sound(
-                            Sound::BlockNoteBlockPling,
-                            SoundCategory::Master,
-                            pos.0,
-                            1.0,
-                            1.0,
-                        );
-                    } else if event.stage == 4 {
+                    if let Some(pitch) = stage.pling {
                         client.play_sound(
                             Sound::BlockNoteBlockPling,
                             SoundCategory::Master,
                             pos.0,
                             1.0,
-                            5.0,
+                            pitch,
                         );
                     }
                 }
@@ -265,21 +523,103 @@ pub fn gamestage_change(
 }
 
 pub fn chat_message(
-    players: Query<(&PlayerGameState, &Username)>,
-    mut clients: Query<(&mut Client, &PlayerGameState)>,
-    mut events: EventReader<ChatMessageEvent>,
+    players: Query<(
+        &PlayerGameState,
+        &GameMode,
+        &Username,
+        Option<&PlayerProfile>,
+        Option<&Spectating>,
+    )>,
+    mut clients: Query<(
+        Entity,
+        &mut Client,
+        &PlayerGameState,
+        &GameMode,
+        Option<&Spectating>,
+    )>,
+    mut events: EventReader<ChatEvent>,
+    chat_format: Res<ChatFormat>,
 ) {
+    // Spectators chat with the game they watch
+    let game_of = |gamestate: &PlayerGameState, spectating: Option<&Spectating>| {
+        gamestate
+            .game_id
+            .or(spectating.map(|spectating| spectating.game_id))
+    };
     for event in events.read() {
-        let Ok((sender_gamestate, sender_name)) = players.get(event.client) else {
+        let Ok((sender_gamestate, sender_mode, username, profile, sender_spectating)) =
+            players.get(event.client)
+        else {
             continue;
         };
-        for (mut client, gamestate) in clients.iter_mut() {
-            if gamestate.game_id == sender_gamestate.game_id {
-                client.send_chat_message(
-                    (String::new() + &sender_name.0 + &String::from(": ") + &event.message)
-                        .color(Color::GRAY),
-                );
+        let Some(game_id) = game_of(sender_gamestate, sender_spectating) else {
+            for (_, mut client, gamestate, _, spectating) in clients.iter_mut() {
+                if game_of(gamestate, spectating).is_none() {
+                    client.send_chat_message(event.text.clone());
+                }
             }
+            continue;
+        };
+
+        let spectating = *sender_mode == GameMode::Spectator || sender_spectating.is_some();
+        let (shout, message) = match event.message.strip_prefix(SHOUT_PREFIX) {
+            Some(message) if !spectating => (true, message.trim()),
+            _ => (false, event.message.as_str()),
+        };
+        if message.is_empty() {
+            continue;
         }
+        let has_teammates = clients.iter().any(|(entity, _, gamestate, mode, _)| {
+            entity != event.client
+                && gamestate.game_id == Some(game_id)
+                && gamestate.team == sender_gamestate.team
+                && *mode != GameMode::Spectator
+        });
+        let channel = if spectating {
+            ChatChannel::Spectators
+        } else if shout || !has_teammates {
+            ChatChannel::All
+        } else {
+            ChatChannel::Team
+        };
+
+        let tag = match channel {
+            ChatChannel::All => format::GOLD.to_string() + "[ALL] ",
+            ChatChannel::Team => format::AQUA.to_string() + "[TEAM] ",
+            ChatChannel::Spectators => format::GRAY.to_string() + "[SPECTATOR] ",
+        };
+        let profile = profile.cloned().unwrap_or_default();
+        let text = tag + &chat_format.message(&profile, &username.0, message);
+
+        for (_, mut client, gamestate, mode, spectating) in clients.iter_mut() {
+            if game_of(gamestate, spectating) != Some(game_id) {
+                continue;
+            }
+            let receives = match channel {
+                ChatChannel::All => true,
+                ChatChannel::Team => {
+                    gamestate.team == sender_gamestate.team
+                        && *mode != GameMode::Spectator
+                        && spectating.is_none()
+                }
+                ChatChannel::Spectators => *mode == GameMode::Spectator || spectating.is_some(),
+            };
+            if receives {
+                client.send_chat_message(text.clone());
+            }
+        }
+    }
+}
+
+fn handle_shout_command(
+    mut events: EventReader<CommandResultEvent<ShoutCommand>>,
+    mut chat: EventWriter<ChatInputEvent>,
+) {
+    // Sent back through the chat pipeline so shouts are filtered like any other message
+    for event in events.read() {
+        chat.send(ChatInputEvent {
+            client: event.executor,
+            message: String::from(SHOUT_PREFIX) + &event.result.message.0,
+        });
     }
 }
@@ -1,5 +1,5 @@
 use crate::death::DeathEvent;
-use crate::duels::{EndGameEvent, PlayerGameState};
+use crate::duels::{EndGameEvent, GameResult, PlayerGameState};
 use std::ops::RangeBounds;
 use valence::prelude::*;
 
@@ -67,7 +67,7 @@ fn handle_oob_clients_end_game<R>(
         {
             end_game.send(EndGameEvent {
                 game_id,
-                loser: gamestate.team,
+                result: GameResult::Loser(gamestate.team),
             });
         }
     }
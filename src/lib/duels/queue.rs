@@ -0,0 +1,127 @@
+use super::matchmaking::{QueueTimes, RecentWaits};
+use super::{Entities, GameSettings, ServerGlobals};
+use crate::network;
+use valence::{
+    Hand, interact_item::InteractItemEvent, inventory::HeldItem, nbt::compound, prelude::*,
+};
+
+/// The last hotbar slot, which holds the "Back to lobby" item while in the queue.
+const LOBBY_ITEM_SLOT: u16 = 44;
+
+pub struct QueuePlugin;
+
+impl Plugin for QueuePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (show_queue_status, update_lobby_items, use_lobby_item),
+        );
+    }
+}
+
+fn format_secs(secs: u64) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+fn show_queue_status(
+    mut clients: Query<&mut Client>,
+    games: Query<(), With<Entities>>,
+    globals: Res<ServerGlobals>,
+    times: Res<QueueTimes>,
+    waits: Res<RecentWaits>,
+    settings: Res<GameSettings>,
+    server: Res<Server>,
+) {
+    // The action bar fades after a few seconds, so it's sent again every second
+    if server.current_tick() % 20 != 0 {
+        return;
+    }
+    let games = games.iter().count();
+    let needed = settings
+        .players_per_game()
+        .saturating_sub(globals.queue.len());
+    for (i, entity) in globals.queue.iter().enumerate() {
+        let Ok(mut client) = clients.get_mut(*entity) else {
+            continue;
+        };
+        let estimate = if needed > 0 {
+            format!(
+                "{} more {} needed",
+                needed,
+                if needed == 1 { "player" } else { "players" }
+            )
+        } else {
+            let waited = times
+                .0
+                .get(entity)
+                .map_or(0.0, |time| time.elapsed().as_secs_f64());
+            match waits.average() {
+                Some(average) if average > waited => {
+                    String::from("~") + &format_secs((average - waited) as u64)
+                }
+                _ => String::from("any moment"),
+            }
+        };
+        client.set_action_bar(
+            Text::from(format!("#{} in queue", i + 1)).color(Color::GOLD)
+                + Text::from(format!(
+                    " | {} waiting | {} {} in progress | Wait: ",
+                    globals.queue.len(),
+                    games,
+                    if games == 1 { "game" } else { "games" }
+                ))
+                .color(Color::GRAY)
+                + Text::from(estimate).color(Color::YELLOW),
+        );
+    }
+}
+
+fn lobby_item() -> ItemStack {
+    ItemStack::new(
+        ItemKind::RedBed,
+        1,
+        Some(compound! {
+            "display" => compound! {
+                "Name" => "{\"text\":\"Back to lobby\",\"italic\":false}"
+            },
+        }),
+    )
+}
+
+/// Hands out the lobby item to queued players and takes it away once they leave the queue.
+fn update_lobby_items(
+    mut clients: Query<(Entity, &mut Inventory), With<Client>>,
+    globals: Res<ServerGlobals>,
+) {
+    if !globals.is_changed() {
+        return;
+    }
+    for (entity, mut inventory) in clients.iter_mut() {
+        let queued = globals.queue.contains(&entity);
+        let slot = inventory.slot(LOBBY_ITEM_SLOT);
+        if queued && slot.is_empty() {
+            inventory.set_slot(LOBBY_ITEM_SLOT, lobby_item());
+        } else if !queued && slot.item == ItemKind::RedBed {
+            inventory.set_slot(LOBBY_ITEM_SLOT, ItemStack::EMPTY);
+        }
+    }
+}
+
+fn use_lobby_item(
+    mut clients: Query<(&mut Client, &Username, &Inventory, &HeldItem)>,
+    mut events: EventReader<InteractItemEvent>,
+    globals: Res<ServerGlobals>,
+) {
+    for event in events.read() {
+        let Ok((mut client, username, inventory, held_item)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        if event.hand == Hand::Main
+            && held_item.slot() == LOBBY_ITEM_SLOT
+            && inventory.slot(LOBBY_ITEM_SLOT).item == ItemKind::RedBed
+            && globals.queue.contains(&event.client)
+        {
+            network::warp(&mut client, &username.0, network::LOBBY);
+        }
+    }
+}
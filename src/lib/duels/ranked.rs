@@ -0,0 +1,364 @@
+use super::{EndGameEvent, EndGameSet, Entities, GameResult, GameSettings, PlayerGameState};
+use crate::config::{DataPath, ServerName, load_section};
+use crate::db::Database;
+use crate::db::models;
+use crate::db::schema::ratings;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use serde::Deserialize;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use valence::log::warn;
+use valence::prelude::*;
+
+#[derive(Deserialize, Clone)]
+pub struct Division {
+    pub name: String,
+    pub min_rating: f64,
+}
+
+#[derive(Resource, Deserialize)]
+#[serde(default)]
+pub struct RankedSettings {
+    pub enabled: bool,
+    pub season: i32,
+    pub default_rating: f64,
+    pub k_factor: f64,
+    /// How much of last season's distance from the default rating is kept after a reset.
+    pub carryover: f64,
+    pub initial_window: f64,
+    /// Rating points the window widens by for every second spent in the queue.
+    pub window_growth: f64,
+    pub max_window: f64,
+    pub divisions: Vec<Division>,
+}
+
+impl Default for RankedSettings {
+    fn default() -> Self {
+        let division = |name: &str, min_rating| Division {
+            name: name.to_string(),
+            min_rating,
+        };
+        Self {
+            enabled: false,
+            season: 1,
+            default_rating: 1000.0,
+            k_factor: 32.0,
+            carryover: 0.5,
+            initial_window: 50.0,
+            window_growth: 10.0,
+            max_window: 500.0,
+            divisions: vec![
+                division("Bronze", 0.0),
+                division("Silver", 1100.0),
+                division("Gold", 1250.0),
+                division("Platinum", 1400.0),
+                division("Diamond", 1600.0),
+                division("Master", 1800.0),
+            ],
+        }
+    }
+}
+
+impl RankedSettings {
+    pub fn window(&self, waited_secs: f64) -> f64 {
+        (self.initial_window + self.window_growth * waited_secs).min(self.max_window)
+    }
+
+    pub fn division(&self, rating: f64) -> &str {
+        self.divisions
+            .iter()
+            .filter(|division| rating >= division.min_rating)
+            .max_by(|a, b| a.min_rating.total_cmp(&b.min_rating))
+            .map_or("Unranked", |division| division.name.as_str())
+    }
+}
+
+/// Marks games that don't change ratings, like direct challenges.
+#[derive(Component)]
+pub struct Unranked;
+
+#[derive(Component)]
+pub struct Rating {
+    pub rating: f64,
+    pub games: i32,
+}
+
+pub struct RankedPlugin;
+
+impl Plugin for RankedPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+        app.insert_resource(LoadedRatings {
+            sender,
+            receiver: Mutex::new(receiver),
+        })
+        .add_systems(Startup, setup)
+        .add_systems(Update, (load_ratings, insert_ratings))
+        .add_systems(PostUpdate, update_ratings.before(EndGameSet));
+    }
+}
+
+/// Ratings are queried on a separate thread so that joining doesn't stall the tick. `None` means
+/// the player has no rating yet or the query failed.
+#[derive(Resource)]
+struct LoadedRatings {
+    sender: Sender<(Entity, Option<models::Rating>)>,
+    receiver: Mutex<Receiver<(Entity, Option<models::Rating>)>>,
+}
+
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    commands.insert_resource(load_section::<RankedSettings>(&data_path.0, "ranked"));
+}
+
+fn load_ratings(
+    mut clients: Query<(Entity, &mut Client, &UniqueId), Added<Client>>,
+    mut commands: Commands,
+    loaded: Res<LoadedRatings>,
+    settings: Res<RankedSettings>,
+    server_name: Res<ServerName>,
+    db: Option<Res<Database>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    for (entity, mut client, uuid) in clients.iter_mut() {
+        // Everyone starts with the default rating until the database responds
+        let rating = Rating {
+            rating: settings.default_rating,
+            games: 0,
+        };
+        let Some(db) = &db else {
+            announce_rating(&mut client, &settings, &rating);
+            commands.entity(entity).insert(rating);
+            continue;
+        };
+        commands.entity(entity).insert(rating);
+
+        let conn = db.0.clone();
+        let sender = loaded.sender.clone();
+        let uuid = uuid.0.as_u128();
+        let minigame = server_name.0.clone();
+        let season = settings.season;
+        thread::spawn(move || {
+            let row = query_rating(&conn, uuid, &minigame, season).unwrap_or_else(|err| {
+                warn!("Failed to load rating of {uuid:x}: {err}");
+                None
+            });
+            let _ = sender.send((entity, row));
+        });
+    }
+}
+
+fn insert_ratings(
+    mut clients: Query<(&mut Client, &mut Rating)>,
+    loaded: Res<LoadedRatings>,
+    settings: Res<RankedSettings>,
+) {
+    let Ok(receiver) = loaded.receiver.lock() else {
+        return;
+    };
+    for (entity, row) in receiver.try_iter() {
+        // The player may have left while their rating was loading
+        let Ok((mut client, mut rating)) = clients.get_mut(entity) else {
+            continue;
+        };
+        // A game finished on the default rating already counts for this season
+        if rating.games == 0 {
+            match row {
+                Some(row) if row.season == settings.season => {
+                    rating.rating = row.rating;
+                    rating.games = row.games;
+                }
+                // Soft reset from the last season the player played
+                Some(row) => {
+                    rating.rating = settings.default_rating
+                        + (row.rating - settings.default_rating) * settings.carryover;
+                }
+                None => {}
+            }
+        }
+        announce_rating(&mut client, &settings, &rating);
+    }
+}
+
+fn announce_rating(client: &mut Client, settings: &RankedSettings, rating: &Rating) {
+    client.send_chat_message(
+        Text::from(format!("Ranked Season {}: ", settings.season)).color(Color::GOLD)
+            + Text::from(format!(
+                "{} ({:.0})",
+                settings.division(rating.rating),
+                rating.rating
+            ))
+            .color(Color::YELLOW),
+    );
+}
+
+/// The player's rating from the given season, or from the last season they played before it.
+fn query_rating(
+    conn: &Mutex<PgConnection>,
+    uuid: u128,
+    minigame: &str,
+    season: i32,
+) -> Result<Option<models::Rating>, Box<dyn Error>> {
+    let mut conn = conn.lock().map_err(|_| "database connection poisoned")?;
+    let id = BigDecimal::from_str(&uuid.to_string())?;
+    Ok(ratings::table
+        .filter(ratings::player_id.eq(&id))
+        .filter(ratings::minigame.eq(minigame))
+        .filter(ratings::season.le(season))
+        .order(ratings::season.desc())
+        .select(models::Rating::as_select())
+        .first(&mut *conn)
+        .optional()?)
+}
+
+fn save_rating(
+    conn: &Mutex<PgConnection>,
+    uuid: u128,
+    minigame: String,
+    season: i32,
+    rating: f64,
+    games: i32,
+) -> Result<(), Box<dyn Error>> {
+    let mut conn = conn.lock().map_err(|_| "database connection poisoned")?;
+    let id = BigDecimal::from_str(&uuid.to_string())?;
+    diesel::insert_into(ratings::table)
+        .values(&models::Rating {
+            player_id: id,
+            minigame,
+            season,
+            rating,
+            games,
+        })
+        .on_conflict((ratings::player_id, ratings::minigame, ratings::season))
+        .do_update()
+        .set((ratings::rating.eq(rating), ratings::games.eq(games)))
+        .execute(&mut *conn)?;
+    Ok(())
+}
+
+fn update_ratings(
+    mut players: Query<(
+        &UniqueId,
+        &PlayerGameState,
+        &mut Rating,
+        Option<&mut Client>,
+    )>,
+    games: Query<&Entities, Without<Unranked>>,
+    mut end_game: EventReader<EndGameEvent>,
+    settings: Res<RankedSettings>,
+    game_settings: Res<GameSettings>,
+    server_name: Res<ServerName>,
+    db: Option<Res<Database>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    let mut handled = Vec::new();
+    for event in end_game.read() {
+        if handled.contains(&event.game_id) {
+            continue;
+        }
+        handled.push(event.game_id);
+        let Ok(entities) = games.get(event.game_id) else {
+            continue;
+        };
+
+        let mut totals = vec![(0.0, 0); game_settings.team_count];
+        for entity in entities.0.iter() {
+            if let Ok((_, gamestate, rating, _)) = players.get(*entity)
+                && let Some(total) = totals.get_mut(gamestate.team as usize)
+            {
+                total.0 += rating.rating;
+                total.1 += 1;
+            }
+        }
+        let averages: Vec<Option<f64>> = totals
+            .iter()
+            .map(|&(sum, count)| (count > 0).then(|| sum / count as f64))
+            .collect();
+
+        for entity in entities.0.iter() {
+            let Ok((uuid, gamestate, mut rating, client)) = players.get_mut(*entity) else {
+                continue;
+            };
+            let left = client.is_none();
+            let team = gamestate.team;
+            let Some(Some(own)) = averages.get(team as usize).copied() else {
+                continue;
+            };
+
+            // Every other team counts as a separate Elo match
+            let mut change = 0.0;
+            let mut opponents = 0;
+            for (other, average) in averages.iter().enumerate() {
+                let Some(average) = average else {
+                    continue;
+                };
+                if other == team as usize {
+                    continue;
+                }
+                let expected = 1.0 / (1.0 + 10f64.powf((average - own) / 400.0));
+                // Players who left before the end always lose
+                let score = match place(&event.result, team).cmp(&place(&event.result, other as u8))
+                {
+                    _ if left => 0.0,
+                    std::cmp::Ordering::Less => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 0.0,
+                };
+                change += score - expected;
+                opponents += 1;
+            }
+            if opponents == 0 {
+                continue;
+            }
+            let change = settings.k_factor * change / opponents as f64;
+            rating.rating += change;
+            rating.games += 1;
+
+            if let Some(mut client) = client {
+                client.send_chat_message(
+                    Text::from("Rating: ").color(Color::GRAY)
+                        + Text::from(format!("{:.0} ", rating.rating)).color(Color::YELLOW)
+                        + Text::from(format!("({:+.0}) ", change)).color(if change >= 0.0 {
+                            Color::GREEN
+                        } else {
+                            Color::RED
+                        })
+                        + Text::from(settings.division(rating.rating).to_string())
+                            .color(Color::GOLD),
+                );
+            }
+
+            if let Some(db) = &db {
+                let conn = db.0.clone();
+                let uuid = uuid.0.as_u128();
+                let minigame = server_name.0.clone();
+                let season = settings.season;
+                let (value, games) = (rating.rating, rating.games);
+                thread::spawn(move || {
+                    if let Err(err) = save_rating(&conn, uuid, minigame, season, value, games) {
+                        warn!("Failed to save rating of {uuid:x}: {err}");
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Lower is better. Teams left out of a ranking share last place.
+fn place(result: &GameResult, team: u8) -> usize {
+    match result {
+        GameResult::Loser(loser) => usize::from(team == *loser),
+        GameResult::Draw => 0,
+        GameResult::Ranking(ranking) => ranking
+            .iter()
+            .position(|&t| t == team)
+            .unwrap_or(ranking.len()),
+    }
+}
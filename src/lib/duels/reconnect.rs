@@ -0,0 +1,174 @@
+#![allow(clippy::type_complexity)]
+
+use super::{Entities, PlayerGameState, ServerGlobals};
+use bevy_ecs::query::QueryData;
+use std::time::Instant;
+use valence::{entity::living::Health, prelude::*};
+
+const RECONNECT_SECS: u64 = 60;
+
+/// A player who dropped out of a running game. Their entity stays in the game, frozen in place,
+/// until they rejoin or the grace period runs out.
+#[derive(Component)]
+pub struct Disconnected {
+    since: Instant,
+}
+
+/// Sent when a player rejoins their game. Subservers should move any per-player game state from
+/// `old` to `new`.
+#[derive(Event)]
+pub struct ReconnectEvent {
+    pub old: Entity,
+    pub new: Entity,
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct PlayerQuery {
+    gamestate: &'static mut PlayerGameState,
+    layer_id: &'static mut EntityLayerId,
+    visible_chunk_layer: &'static mut VisibleChunkLayer,
+    visible_entity_layers: &'static mut VisibleEntityLayers,
+    pos: &'static mut Position,
+    look: &'static mut Look,
+    yaw: &'static mut HeadYaw,
+    inventory: &'static mut Inventory,
+    health: &'static mut Health,
+    game_mode: &'static mut GameMode,
+}
+
+pub struct ReconnectPlugin;
+
+impl Plugin for ReconnectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ReconnectEvent>()
+            .add_systems(Update, (handle_disconnected_clients, expire_disconnected));
+    }
+}
+
+/// Replaces valence's `despawn_disconnected_clients`, keeping players in a game around for a while.
+pub fn handle_disconnected_clients(
+    mut removed: RemovedComponents<Client>,
+    players: Query<(&PlayerGameState, &Username)>,
+    mut clients: Query<(&mut Client, &PlayerGameState)>,
+    mut commands: Commands,
+) {
+    for entity in removed.read() {
+        let Some(mut entity_commands) = commands.get_entity(entity) else {
+            continue;
+        };
+        let Ok((gamestate, username)) = players.get(entity) else {
+            entity_commands.insert(Despawned);
+            continue;
+        };
+        if gamestate.game_id.is_none() {
+            entity_commands.insert(Despawned);
+            continue;
+        }
+
+        entity_commands.insert(Disconnected {
+            since: Instant::now(),
+        });
+        for (mut client, other) in clients.iter_mut() {
+            if other.game_id == gamestate.game_id {
+                client.send_chat_message(
+                    (username.0.clone()
+                        + " disconnected! They have "
+                        + itoa::Buffer::new().format(RECONNECT_SECS)
+                        + " seconds to reconnect.")
+                        .color(Color::GRAY),
+                );
+            }
+        }
+    }
+}
+
+fn expire_disconnected(
+    mut bodies: Query<(Entity, &Disconnected, &mut PlayerGameState)>,
+    games: Query<&Entities>,
+    mut commands: Commands,
+) {
+    for (entity, disconnected, mut gamestate) in bodies.iter_mut() {
+        let running = gamestate
+            .game_id
+            .is_some_and(|game_id| games.contains(game_id));
+        if !running {
+            // The game ended without them
+            gamestate.game_id = None;
+        } else if disconnected.since.elapsed().as_secs() < RECONNECT_SECS {
+            continue;
+        }
+        // Ends the game through `handle_disconnect` if the player's game is still running
+        commands
+            .entity(entity)
+            .remove::<Disconnected>()
+            .insert(Despawned);
+    }
+}
+
+/// Puts rejoining players back into their game. Must run after the map's `init_clients`, which
+/// queues every new client.
+pub fn reconnect(
+    mut clients: Query<(Entity, &UniqueId, &mut Client, &Username, PlayerQuery)>,
+    mut bodies: Query<(Entity, &UniqueId, PlayerQuery), (With<Disconnected>, Without<Client>)>,
+    mut games: Query<&mut Entities>,
+    mut reconnects: EventWriter<ReconnectEvent>,
+    mut commands: Commands,
+    mut globals: ResMut<ServerGlobals>,
+) {
+    let mut reconnected = Vec::new();
+    for (entity, uuid, mut client, username, mut player) in clients.iter_mut() {
+        if !client.is_added() {
+            continue;
+        }
+        let Some((body, _, mut old)) = bodies.iter_mut().find(|(_, id, _)| id.0 == uuid.0) else {
+            continue;
+        };
+        let Some(game_id) = old.gamestate.game_id else {
+            continue;
+        };
+        let Ok(mut entities) = games.get_mut(game_id) else {
+            continue;
+        };
+        for player in entities.0.iter_mut() {
+            if *player == body {
+                *player = entity;
+            }
+        }
+        globals.queue.retain(|&queued| queued != entity);
+
+        player.gamestate.game_id = Some(game_id);
+        player.gamestate.team = old.gamestate.team;
+        player.gamestate.slot = old.gamestate.slot;
+        player.gamestate.wins = old.gamestate.wins;
+        player.layer_id.0 = old.layer_id.0;
+        player.visible_chunk_layer.0 = old.visible_chunk_layer.0;
+        player.visible_entity_layers.0 = old.visible_entity_layers.0.clone();
+        player.pos.set(old.pos.0);
+        player.look.yaw = old.look.yaw;
+        player.look.pitch = old.look.pitch;
+        player.yaw.0 = old.yaw.0;
+        *player.inventory = old.inventory.clone();
+        player.health.0 = old.health.0;
+        *player.game_mode = *old.game_mode;
+
+        // The old entity no longer counts as a player leaving the game
+        old.gamestate.game_id = None;
+        commands.entity(body).insert(Despawned);
+        reconnects.send(ReconnectEvent {
+            old: body,
+            new: entity,
+        });
+
+        client.send_chat_message("Reconnected to your game!".color(Color::GREEN));
+        reconnected.push((entity, game_id, username.0.clone()));
+    }
+
+    for (entity, game_id, username) in reconnected {
+        for (other, _, mut client, _, player) in clients.iter_mut() {
+            if other != entity && player.gamestate.game_id == Some(game_id) {
+                client.send_chat_message((username.clone() + " reconnected!").color(Color::GREEN));
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+#![allow(clippy::type_complexity)]
+
+use super::{
+    DuelsConfig, EndGameEvent, EndGameSet, Entities, GameSettings, MapIndex, Match,
+    PlayerGameState, ServerGlobals,
+};
+use std::marker::PhantomData;
+use std::time::Instant;
+use valence::{
+    command::{AddCommand, handler::CommandResultEvent},
+    command_macros::Command,
+    prelude::*,
+};
+
+const REMATCH_SECS: u64 = 15;
+
+#[derive(Command, Debug, Clone)]
+#[paths("rematch {map?}")]
+#[scopes("minibit.commands.all.rematch")]
+struct RematchCommand {
+    /// `same` to play on the same map again.
+    map: Option<String>,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("playagain", "requeue")]
+#[scopes("minibit.commands.all.playagain")]
+struct PlayAgainCommand {}
+
+/// Players of a finished game. They go straight back to the queue, and are only taken out of it
+/// once one of them asks for a rematch.
+struct RematchOffer {
+    /// In team order, so a rematch keeps the same teams.
+    players: Vec<Entity>,
+    map: usize,
+    /// Players who want a rematch, and whether they asked for the same map.
+    accepted: Vec<(Entity, bool)>,
+    ended: Instant,
+}
+
+#[derive(Resource, Default)]
+struct RematchOffers(Vec<RematchOffer>);
+
+pub struct RematchPlugin<T: Resource + DuelsConfig> {
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Resource + DuelsConfig + Sync + Send + 'static> Plugin for RematchPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_command::<RematchCommand>()
+            .add_command::<PlayAgainCommand>()
+            .init_resource::<RematchOffers>()
+            .add_systems(
+                Update,
+                (
+                    handle_rematch_command,
+                    handle_play_again_command,
+                    expire_offers,
+                ),
+            )
+            .add_systems(PostUpdate, offer_rematches::<T>.before(EndGameSet));
+    }
+}
+
+fn offer_rematches<T: Resource + DuelsConfig>(
+    mut clients: Query<&mut Client, Without<Despawned>>,
+    games: Query<(&Entities, &MapIndex)>,
+    mut end_game: EventReader<EndGameEvent>,
+    mut offers: ResMut<RematchOffers>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    let mut handled = Vec::new();
+    for event in end_game.read() {
+        if handled.contains(&event.game_id) {
+            continue;
+        }
+        handled.push(event.game_id);
+        let Ok((entities, map)) = games.get(event.game_id) else {
+            continue;
+        };
+        let players: Vec<Entity> = entities
+            .0
+            .iter()
+            .copied()
+            .filter(|entity| clients.contains(*entity))
+            .collect();
+        // Someone left, so there is nobody to rematch
+        if players.len() < settings.players_per_game() {
+            continue;
+        }
+
+        for entity in players.iter() {
+            if let Ok(mut client) = clients.get_mut(*entity) {
+                client.send_chat_message(
+                    Text::from("[Rematch]")
+                        .color(Color::GREEN)
+                        .on_click_run_command("/rematch")
+                        .on_hover_show_text("Play the same players on a new map")
+                        + Text::from(" ")
+                        + Text::from(
+                            "[Rematch on ".to_string() + config.worlds()[map.0].name() + "]",
+                        )
+                        .color(Color::GREEN)
+                        .on_click_run_command("/rematch same")
+                        .on_hover_show_text("Play the same players on the same map")
+                        + Text::from(" ")
+                        + Text::from("[Play again]")
+                            .color(Color::AQUA)
+                            .on_click_run_command("/playagain")
+                            .on_hover_show_text("Find a new opponent"),
+                );
+            }
+        }
+        offers.0.push(RematchOffer {
+            players,
+            map: map.0,
+            accepted: Vec::new(),
+            ended: Instant::now(),
+        });
+    }
+}
+
+fn handle_rematch_command(
+    mut events: EventReader<CommandResultEvent<RematchCommand>>,
+    mut clients: Query<(&mut Client, &Username)>,
+    players: Query<&PlayerGameState>,
+    mut offers: ResMut<RematchOffers>,
+    mut globals: ResMut<ServerGlobals>,
+) {
+    for event in events.read() {
+        let Some(index) = offers
+            .0
+            .iter()
+            .position(|offer| offer.players.contains(&event.executor))
+        else {
+            if let Ok((mut client, _)) = clients.get_mut(event.executor) {
+                client.send_chat_message("You have nobody to rematch!".color(Color::RED));
+            }
+            continue;
+        };
+        // Anyone who already found a new game can't be pulled back out of it
+        let busy = offers.0[index].players.iter().copied().find(|entity| {
+            players
+                .get(*entity)
+                .is_ok_and(|gamestate| gamestate.game_id.is_some())
+        });
+        if let Some(busy) = busy {
+            let name = clients
+                .get(busy)
+                .map_or(String::new(), |(_, username)| username.0.clone());
+            let offer = offers.0.remove(index);
+            for entity in offer.players.iter() {
+                if let Ok((mut client, _)) = clients.get_mut(*entity)
+                    && *entity != busy
+                {
+                    client.send_chat_message(
+                        (name.clone() + " is already in another game, you are back in the queue.")
+                            .color(Color::GRAY),
+                    );
+                }
+            }
+            if !offer.accepted.is_empty() {
+                requeue(
+                    &mut globals,
+                    offer
+                        .players
+                        .iter()
+                        .filter(|entity| **entity != busy && clients.contains(**entity)),
+                );
+            }
+            continue;
+        }
+
+        // Everyone waits for the others to answer instead of being matched with someone else
+        let offer = &mut offers.0[index];
+        globals
+            .queue
+            .retain(|queued| !offer.players.contains(queued));
+        let same_map = event
+            .result
+            .map
+            .as_ref()
+            .is_some_and(|map| map.eq_ignore_ascii_case("same"));
+        offer
+            .accepted
+            .retain(|(entity, _)| *entity != event.executor);
+        offer.accepted.push((event.executor, same_map));
+
+        if offer.accepted.len() < offer.players.len() {
+            let Ok((_, username)) = clients.get(event.executor) else {
+                continue;
+            };
+            let message = Text::from(username.0.clone() + " wants a rematch! ").color(Color::GOLD)
+                + Text::from("[Rematch]")
+                    .color(Color::GREEN)
+                    .on_click_run_command(if same_map {
+                        "/rematch same"
+                    } else {
+                        "/rematch"
+                    });
+            for entity in offer.players.iter() {
+                if offer
+                    .accepted
+                    .iter()
+                    .any(|(accepted, _)| accepted == entity)
+                {
+                    continue;
+                }
+                if let Ok((mut client, _)) = clients.get_mut(*entity) {
+                    client.send_chat_message(message.clone());
+                }
+            }
+            if let Ok((mut client, _)) = clients.get_mut(event.executor) {
+                client.send_chat_message("Waiting for the others to accept...".color(Color::GRAY));
+            }
+            continue;
+        }
+
+        let offer = offers.0.remove(index);
+        // The same map is only used if everyone asked for it
+        let map = offer
+            .accepted
+            .iter()
+            .all(|(_, same_map)| *same_map)
+            .then_some(offer.map);
+        for entity in offer.players.iter() {
+            if let Ok((mut client, _)) = clients.get_mut(*entity) {
+                client.send_chat_message("Rematch accepted!".color(Color::GREEN));
+            }
+        }
+        globals.matches.push(Match {
+            players: offer.players,
+            map,
+            ranked: false,
+        });
+    }
+}
+
+fn handle_play_again_command(
+    mut events: EventReader<CommandResultEvent<PlayAgainCommand>>,
+    mut clients: Query<(&mut Client, &Username)>,
+    mut offers: ResMut<RematchOffers>,
+    mut globals: ResMut<ServerGlobals>,
+) {
+    for event in events.read() {
+        // Nobody asked for a rematch yet, so the players are still queued
+        let Some(index) = offers.0.iter().position(|offer| {
+            offer.players.contains(&event.executor) && !offer.accepted.is_empty()
+        }) else {
+            if let Ok((mut client, _)) = clients.get_mut(event.executor) {
+                client.send_chat_message(if globals.queue.contains(&event.executor) {
+                    "You are already in the queue!".color(Color::RED)
+                } else {
+                    "You can't do that right now!".color(Color::RED)
+                });
+            }
+            continue;
+        };
+
+        // A rematch needs everyone, so the others go back to the queue as well
+        let offer = offers.0.remove(index);
+        let username = clients
+            .get(event.executor)
+            .map_or(String::new(), |(_, username)| username.0.clone());
+        for entity in offer.players.iter() {
+            if let Ok((mut client, _)) = clients.get_mut(*entity) {
+                client.send_chat_message(if *entity == event.executor {
+                    "Looking for a new game...".color(Color::GREEN)
+                } else {
+                    (username.clone() + " left, you are back in the queue.").color(Color::GRAY)
+                });
+            }
+        }
+        requeue(
+            &mut globals,
+            offer
+                .players
+                .iter()
+                .filter(|entity| clients.contains(**entity)),
+        );
+    }
+}
+
+fn expire_offers(
+    mut clients: Query<&mut Client, Without<Despawned>>,
+    mut offers: ResMut<RematchOffers>,
+    mut globals: ResMut<ServerGlobals>,
+) {
+    offers.0.retain(|offer| {
+        let left = offer
+            .players
+            .iter()
+            .any(|entity| !clients.contains(*entity));
+        if !left && offer.ended.elapsed().as_secs() < REMATCH_SECS {
+            return true;
+        }
+        if offer.accepted.is_empty() {
+            return false;
+        }
+        for entity in offer.players.iter() {
+            if let Ok(mut client) = clients.get_mut(*entity) {
+                client.send_chat_message(
+                    "The rematch didn't happen, you are back in the queue.".color(Color::GRAY),
+                );
+            }
+        }
+        requeue(
+            &mut globals,
+            offer
+                .players
+                .iter()
+                .filter(|entity| clients.contains(**entity)),
+        );
+        false
+    });
+}
+
+/// Puts players held for a rematch back into the queue, once someone has accepted and taken them
+/// out of it.
+fn requeue<'a>(globals: &mut ServerGlobals, players: impl Iterator<Item = &'a Entity>) {
+    for entity in players {
+        if !globals.queue.contains(entity) {
+            globals.queue.push(*entity);
+        }
+    }
+}
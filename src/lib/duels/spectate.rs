@@ -0,0 +1,334 @@
+#![allow(clippy::type_complexity)]
+
+use super::{EndGameEvent, EndGameSet, Entities, PlayerGameState, ServerGlobals};
+use crate::network;
+use crate::reports::SpectateLinks;
+use bevy_ecs::query::QueryData;
+use valence::{
+    command::{AddCommand, handler::CommandResultEvent},
+    command_macros::Command,
+    inventory::ClickSlotEvent,
+    nbt::compound,
+    prelude::*,
+};
+
+#[derive(Command, Debug, Clone)]
+#[paths("spectate {target?}", "spec {target?}")]
+#[scopes("minibit.commands.all.spectate")]
+struct SpectateCommand {
+    target: Option<String>,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("leave")]
+#[scopes("minibit.commands.all.leave")]
+struct LeaveCommand {}
+
+/// Added to players watching a game. Keeps what they saw before so it can be restored.
+#[derive(Component)]
+pub struct Spectating {
+    pub game_id: Entity,
+    game_layer: Entity,
+    chunk_layer: Entity,
+    game_mode: GameMode,
+    position: DVec3,
+}
+
+/// Spectator clients can't use items, so the compass menu is opened with `/spectate` instead.
+#[derive(Component)]
+struct CompassMenu {
+    inventory: Entity,
+    targets: Vec<Entity>,
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+struct SpectatorQuery {
+    client: &'static mut Client,
+    layer_id: &'static EntityLayerId,
+    visible_chunk_layer: &'static mut VisibleChunkLayer,
+    visible_entity_layers: &'static mut VisibleEntityLayers,
+    pos: &'static mut Position,
+    game_mode: &'static mut GameMode,
+    spectating: Option<&'static Spectating>,
+}
+
+pub struct SpectatePlugin;
+
+impl Plugin for SpectatePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpectateLinks)
+            .add_command::<SpectateCommand>()
+            .add_command::<LeaveCommand>()
+            .add_systems(
+                Update,
+                (handle_spectate_command, handle_leave_command, close_menus),
+            )
+            .add_systems(EventLoopUpdate, handle_menu_click)
+            .add_systems(PostUpdate, leave_finished_games.before(EndGameSet));
+    }
+}
+
+fn handle_spectate_command(
+    mut events: EventReader<CommandResultEvent<SpectateCommand>>,
+    mut clients: Query<SpectatorQuery>,
+    players: Query<(Entity, &Username, &PlayerGameState)>,
+    games: Query<&Entities>,
+    mut commands: Commands,
+    mut globals: ResMut<ServerGlobals>,
+) {
+    for event in events.read() {
+        let Some(name) = &event.result.target else {
+            let Ok(mut spectator) = clients.get_mut(event.executor) else {
+                continue;
+            };
+            let Some(spectating) = spectator.spectating else {
+                spectator.client.send_chat_message(
+                    "You are not spectating anyone! Use /spectate <player>.".color(Color::RED),
+                );
+                continue;
+            };
+            let Ok(entities) = games.get(spectating.game_id) else {
+                continue;
+            };
+            let targets: Vec<(Entity, &str)> = entities
+                .0
+                .iter()
+                .filter_map(|entity| players.get(*entity).ok())
+                .map(|(entity, username, _)| (entity, username.0.as_str()))
+                .collect();
+            let inventory = commands.spawn(compass_menu(&targets)).id();
+            commands.entity(event.executor).insert((
+                CompassMenu {
+                    inventory,
+                    targets: targets.iter().map(|(entity, _)| *entity).collect(),
+                },
+                OpenInventory::new(inventory),
+            ));
+            continue;
+        };
+
+        let target = players
+            .iter()
+            .find(|(_, username, _)| username.0.eq_ignore_ascii_case(name));
+        let view = match target {
+            Some((entity, _, gamestate)) if entity != event.executor => gamestate
+                .game_id
+                .zip(clients.get(entity).ok())
+                .map(|(game_id, target)| {
+                    (
+                        game_id,
+                        target.layer_id.0,
+                        target.visible_chunk_layer.0,
+                        target.pos.0,
+                    )
+                }),
+            _ => None,
+        };
+        let Ok(mut spectator) = clients.get_mut(event.executor) else {
+            continue;
+        };
+        let Some((game_id, game_layer, chunk_layer, position)) = view else {
+            let message = match target {
+                Some((entity, _, _)) if entity == event.executor => {
+                    String::from("You can't spectate yourself!")
+                }
+                Some((_, username, _)) => username.0.clone() + " is not in a game!",
+                None => match network::find_player(name) {
+                    Some(player) => {
+                        player.username + " is playing on " + &player.server + ", not this game!"
+                    }
+                    None => String::from("Player ") + name + " is not online!",
+                },
+            };
+            spectator
+                .client
+                .send_chat_message(message.color(Color::RED));
+            continue;
+        };
+
+        let state = match spectator.spectating {
+            // Switching games keeps what to go back to
+            Some(spectating) => {
+                spectator
+                    .visible_entity_layers
+                    .0
+                    .remove(&spectating.game_layer);
+                Spectating {
+                    game_id,
+                    game_layer,
+                    chunk_layer: spectating.chunk_layer,
+                    game_mode: spectating.game_mode,
+                    position: spectating.position,
+                }
+            }
+            None => {
+                if !globals.queue.contains(&event.executor) {
+                    spectator
+                        .client
+                        .send_chat_message("You can't spectate while in a game!".color(Color::RED));
+                    continue;
+                }
+                globals.queue.retain(|&entity| entity != event.executor);
+                let own_layer = spectator.layer_id.0;
+                spectator.visible_entity_layers.0.remove(&own_layer);
+                Spectating {
+                    game_id,
+                    game_layer,
+                    chunk_layer: spectator.visible_chunk_layer.0,
+                    game_mode: *spectator.game_mode,
+                    position: spectator.pos.0,
+                }
+            }
+        };
+
+        spectator.visible_chunk_layer.0 = chunk_layer;
+        spectator.visible_entity_layers.0.insert(game_layer);
+        *spectator.game_mode = GameMode::Spectator;
+        spectator.pos.set(position);
+        commands.entity(event.executor).insert(state);
+
+        spectator.client.send_chat_message(
+            Text::from("You are now spectating. ").color(Color::GREEN)
+                + Text::from("[Teleport]")
+                    .color(Color::AQUA)
+                    .on_click_run_command("/spectate")
+                    .on_hover_show_text("Teleport to a player")
+                + Text::from(" ")
+                + Text::from("[Leave]")
+                    .color(Color::RED)
+                    .on_click_run_command("/leave"),
+        );
+    }
+}
+
+/// Spectators go back to the queue, queued players go back to the lobby.
+fn handle_leave_command(
+    mut events: EventReader<CommandResultEvent<LeaveCommand>>,
+    mut clients: Query<SpectatorQuery>,
+    usernames: Query<&Username>,
+    mut commands: Commands,
+    mut globals: ResMut<ServerGlobals>,
+) {
+    for event in events.read() {
+        let Ok(mut spectator) = clients.get_mut(event.executor) else {
+            continue;
+        };
+        let Some(spectating) = spectator.spectating else {
+            if !globals.queue.contains(&event.executor) {
+                spectator
+                    .client
+                    .send_chat_message("You can't leave during a game!".color(Color::RED));
+            } else if let Ok(username) = usernames.get(event.executor) {
+                network::warp(&mut spectator.client, &username.0, network::LOBBY);
+            }
+            continue;
+        };
+        stop_spectating(&mut spectator, spectating);
+        commands.entity(event.executor).remove::<Spectating>();
+        globals.queue.push(event.executor);
+    }
+}
+
+fn leave_finished_games(
+    mut clients: Query<(Entity, SpectatorQuery)>,
+    mut end_game: EventReader<EndGameEvent>,
+    mut commands: Commands,
+    mut globals: ResMut<ServerGlobals>,
+) {
+    let ended: Vec<Entity> = end_game.read().map(|event| event.game_id).collect();
+    if ended.is_empty() {
+        return;
+    }
+    for (entity, mut spectator) in clients.iter_mut() {
+        let Some(spectating) = spectator.spectating else {
+            continue;
+        };
+        if !ended.contains(&spectating.game_id) {
+            continue;
+        }
+        stop_spectating(&mut spectator, spectating);
+        spectator
+            .client
+            .send_chat_message("The game you were spectating has ended.");
+        commands.entity(entity).remove::<Spectating>();
+        globals.queue.push(entity);
+    }
+}
+
+fn stop_spectating(spectator: &mut SpectatorQueryItem, spectating: &Spectating) {
+    let own_layer = spectator.layer_id.0;
+    spectator
+        .visible_entity_layers
+        .0
+        .remove(&spectating.game_layer);
+    spectator.visible_entity_layers.0.insert(own_layer);
+    spectator.visible_chunk_layer.0 = spectating.chunk_layer;
+    *spectator.game_mode = spectating.game_mode;
+    spectator.pos.set(spectating.position);
+}
+
+fn compass_menu(targets: &[(Entity, &str)]) -> Inventory {
+    let kind = match targets.len() {
+        0..=9 => InventoryKind::Generic9x1,
+        10..=18 => InventoryKind::Generic9x2,
+        _ => InventoryKind::Generic9x3,
+    };
+    let mut inventory = Inventory::with_title(kind, "Spectator Compass");
+    inventory.readonly = true;
+    for (i, (_, username)) in targets.iter().take(27).enumerate() {
+        inventory.set_slot(
+            i as u16,
+            ItemStack::new(
+                ItemKind::PlayerHead,
+                1,
+                Some(compound! {
+                    "display" => compound! {
+                        "Name" => format!("{{\"text\":\"{}\",\"italic\":false}}", username)
+                    },
+                    "SkullOwner" => compound! {
+                        "Name" => *username
+                    }
+                }),
+            ),
+        );
+    }
+    inventory
+}
+
+fn handle_menu_click(
+    mut clients: Query<(&mut Position, &CompassMenu)>,
+    targets: Query<&Position, Without<CompassMenu>>,
+    mut click_slot: EventReader<ClickSlotEvent>,
+    mut commands: Commands,
+) {
+    for event in click_slot.read() {
+        let Ok((mut pos, menu)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        if event.window_id == 0 || event.slot_id < 0 {
+            continue;
+        }
+        let Some(target) = menu.targets.get(event.slot_id as usize) else {
+            continue;
+        };
+
+        if let Ok(target_pos) = targets.get(*target) {
+            pos.set(target_pos.0);
+        }
+        commands.entity(menu.inventory).insert(Despawned);
+        commands
+            .entity(event.client)
+            .remove::<(CompassMenu, OpenInventory)>();
+    }
+}
+
+fn close_menus(
+    clients: Query<(Entity, &CompassMenu), Without<OpenInventory>>,
+    mut commands: Commands,
+) {
+    for (entity, menu) in clients.iter() {
+        commands.entity(menu.inventory).insert(Despawned);
+        commands.entity(entity).remove::<CompassMenu>();
+    }
+}
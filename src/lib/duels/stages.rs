@@ -0,0 +1,112 @@
+use super::{GameStage, GameStageEvent};
+use crate::config::{DataPath, load_section};
+use serde::Deserialize;
+use std::time::Instant;
+use valence::prelude::*;
+
+/// One step of the schedule every round goes through. Stages are numbered by their position in
+/// the schedule and announced with `GameStageEvent`.
+#[derive(Deserialize, Clone)]
+pub struct Stage {
+    /// Seconds before the next stage starts. The last stage lasts until the round ends.
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub title: Option<Text>,
+    #[serde(default)]
+    pub clear_title: bool,
+    /// Pitch of a note block pling played when the stage starts.
+    #[serde(default)]
+    pub pling: Option<f32>,
+    /// Keeps players at their spawn for the whole stage.
+    #[serde(default)]
+    pub freeze: bool,
+}
+
+#[derive(Resource, Deserialize, Clone)]
+pub struct StageSchedule(pub Vec<Stage>);
+
+impl Default for StageSchedule {
+    /// A 3/2/1/GO countdown with players held at their spawns.
+    fn default() -> Self {
+        let countdown = |title: Text, pling: f32, freeze: bool| Stage {
+            duration: 1.0,
+            title: Some(title),
+            clear_title: false,
+            pling: Some(pling),
+            freeze,
+        };
+        Self(vec![
+            Stage {
+                duration: 0.0,
+                title: None,
+                clear_title: false,
+                pling: None,
+                freeze: true,
+            },
+            countdown("3".color(Color::GREEN), 1.0, true),
+            countdown("2".color(Color::GOLD), 1.0, true),
+            countdown("1".color(Color::RED), 1.0, true),
+            countdown("GO!".color(Color::RED), 5.0, false),
+            Stage {
+                duration: 0.0,
+                title: None,
+                clear_title: true,
+                pling: None,
+                freeze: false,
+            },
+        ])
+    }
+}
+
+impl StageSchedule {
+    /// Seconds into the round at which `stage` ends, or `None` for the last stage.
+    pub fn end_of(&self, stage: u8) -> Option<f64> {
+        let stage = stage as usize;
+        (stage + 1 < self.0.len()).then(|| self.0[..=stage].iter().map(|s| s.duration).sum())
+    }
+
+    pub fn freezes(&self, stage: u8) -> bool {
+        self.0.get(stage as usize).is_some_and(|stage| stage.freeze)
+    }
+}
+
+/// When the current round of a game started. Unlike `GameTime` this is reset for every round.
+#[derive(Component)]
+pub struct RoundTime(pub Instant);
+
+/// Restarts a game's stage schedule from the first stage, e.g. after a point is scored.
+#[derive(Event)]
+pub struct NewRoundEvent(pub Entity);
+
+pub struct StagePlugin;
+
+impl Plugin for StagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<NewRoundEvent>()
+            .add_systems(Startup, setup)
+            .add_systems(Update, start_rounds);
+    }
+}
+
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    commands.insert_resource(load_section::<StageSchedule>(&data_path.0, "stages"));
+}
+
+fn start_rounds(
+    mut games: Query<(&mut GameStage, &mut RoundTime)>,
+    mut rounds: EventReader<NewRoundEvent>,
+    mut gamestage: EventWriter<GameStageEvent>,
+) {
+    for NewRoundEvent(game_id) in rounds.read() {
+        let Ok((mut stage, mut round)) = games.get_mut(*game_id) else {
+            continue;
+        };
+        round.0 = Instant::now();
+        stage.0 = 0;
+        gamestage.send(GameStageEvent {
+            game_id: *game_id,
+            stage: 0,
+        });
+    }
+}
@@ -0,0 +1,282 @@
+#![allow(clippy::type_complexity)]
+
+use super::{EndGameEvent, EndGameSet, Entities, GameResult, StartGameEvent};
+use crate::config::{DataPath, load_section};
+use serde::Deserialize;
+use std::time::Instant;
+use valence::{
+    boss_bar::{
+        BossBarBundle, BossBarColor, BossBarDivision, BossBarHealth, BossBarStyle, BossBarTitle,
+    },
+    entity::living::Health,
+    prelude::*,
+};
+
+/// What happens when a game runs out of time.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tiebreak {
+    /// The team with the highest score wins, or the game is a draw if scores are tied.
+    Score,
+    /// Like `Score`, but tied teams drop to half a heart and play on until the next point. The
+    /// game is a draw if nobody scores in time.
+    SuddenDeath,
+    Draw,
+}
+
+#[derive(Resource, Deserialize)]
+#[serde(default)]
+pub struct TimeLimitSettings {
+    /// Seconds a game can last, counting the countdown. 0 disables the limit.
+    pub time_limit: u64,
+    pub tiebreak: Tiebreak,
+    pub sudden_death: u64,
+}
+
+impl Default for TimeLimitSettings {
+    fn default() -> Self {
+        Self {
+            time_limit: 0,
+            tiebreak: Tiebreak::Score,
+            sudden_death: 60,
+        }
+    }
+}
+
+/// Points per team, used to break ties when time runs out. Subservers that keep score should
+/// update this alongside their own state.
+#[derive(Component, Default, Clone, PartialEq)]
+pub struct TeamScores(pub Vec<i32>);
+
+impl TeamScores {
+    pub fn new(team_count: usize) -> Self {
+        Self(vec![0; team_count])
+    }
+
+    pub fn add(&mut self, team: u8, points: i32) {
+        let team = team as usize;
+        if self.0.len() <= team {
+            self.0.resize(team + 1, 0);
+        }
+        self.0[team] += points;
+    }
+
+    /// Teams ordered from the highest score to the lowest, or `Draw` if the top score is shared.
+    /// Teams without an entry count as 0 points.
+    pub fn result(&self) -> GameResult {
+        let mut teams: Vec<u8> = (0..self.0.len() as u8).collect();
+        teams.sort_by_key(|&team| std::cmp::Reverse(self.0[team as usize]));
+        let second = teams.get(1).map_or(0, |&team| self.0[team as usize]);
+        match teams.first() {
+            Some(&first) if self.0[first as usize] > second => GameResult::Ranking(teams),
+            _ => GameResult::Draw,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct TimeLimit {
+    started: Instant,
+    boss_bar: Entity,
+    /// Seconds currently shown on the boss bar.
+    shown: u64,
+    /// When sudden death started and the scores at that moment.
+    sudden_death: Option<(Instant, TeamScores)>,
+}
+
+pub struct TimeLimitPlugin;
+
+impl Plugin for TimeLimitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(Update, (start_timers, tick_timers))
+            .add_systems(PostUpdate, remove_boss_bars.before(EndGameSet));
+    }
+}
+
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    commands.insert_resource(load_section::<TimeLimitSettings>(
+        &data_path.0,
+        "time_limit",
+    ));
+}
+
+fn start_timers(
+    games: Query<&EntityLayerId, With<Entities>>,
+    mut start_game: EventReader<StartGameEvent>,
+    mut commands: Commands,
+    settings: Res<TimeLimitSettings>,
+) {
+    if settings.time_limit == 0 {
+        return;
+    }
+    for StartGameEvent(game_id) in start_game.read() {
+        let Ok(layer) = games.get(*game_id) else {
+            continue;
+        };
+        // The boss bar is on the game's layer, so spectators see it too
+        let boss_bar = commands
+            .spawn(BossBarBundle {
+                title: BossBarTitle(time_text(settings.time_limit, false)),
+                health: BossBarHealth(1.0),
+                style: BossBarStyle {
+                    color: BossBarColor::Yellow,
+                    division: BossBarDivision::NoDivision,
+                },
+                layer: *layer,
+                ..Default::default()
+            })
+            .id();
+        commands.entity(*game_id).insert(TimeLimit {
+            started: Instant::now(),
+            boss_bar,
+            shown: settings.time_limit,
+            sudden_death: None,
+        });
+    }
+}
+
+fn time_text(secs: u64, sudden_death: bool) -> Text {
+    let time = format!("{}:{:02}", secs / 60, secs % 60);
+    if sudden_death {
+        Text::from("SUDDEN DEATH ").color(Color::RED).bold() + Text::from(time).color(Color::WHITE)
+    } else {
+        Text::from("Time left: ").color(Color::YELLOW) + Text::from(time).color(Color::WHITE)
+    }
+}
+
+fn tick_timers(
+    mut clients: Query<(&mut Client, &mut Health)>,
+    mut games: Query<(Entity, &mut TimeLimit, &TeamScores, &Entities)>,
+    mut boss_bars: Query<(&mut BossBarTitle, &mut BossBarHealth, &mut BossBarStyle)>,
+    mut end_game: EventWriter<EndGameEvent>,
+    settings: Res<TimeLimitSettings>,
+) {
+    for (game_id, mut timer, scores, entities) in games.iter_mut() {
+        let Ok((mut title, mut progress, mut style)) = boss_bars.get_mut(timer.boss_bar) else {
+            continue;
+        };
+
+        if let Some((started, before)) = timer.sudden_death.clone() {
+            let elapsed = started.elapsed().as_secs();
+            if *scores != before || elapsed >= settings.sudden_death {
+                let result = scores.result();
+                if *scores != before && matches!(result, GameResult::Draw) {
+                    // Both teams scored at once, keep going
+                    timer.sudden_death = Some((started, scores.clone()));
+                    continue;
+                }
+                end_game.send(EndGameEvent { game_id, result });
+                continue;
+            }
+            let remaining = settings.sudden_death - elapsed;
+            if remaining == timer.shown {
+                continue;
+            }
+            timer.shown = remaining;
+            title.0 = time_text(remaining, true);
+            progress.0 = remaining as f32 / settings.sudden_death.max(1) as f32;
+            continue;
+        }
+
+        let elapsed = timer.started.elapsed().as_secs();
+        if elapsed < settings.time_limit {
+            let remaining = settings.time_limit - elapsed;
+            // Only touch the boss bar when the text changes, every change is sent to the clients
+            if remaining == timer.shown {
+                continue;
+            }
+            timer.shown = remaining;
+            title.0 = time_text(remaining, false);
+            progress.0 = remaining as f32 / settings.time_limit as f32;
+            continue;
+        }
+
+        match settings.tiebreak {
+            Tiebreak::Draw => {
+                end_game.send(EndGameEvent {
+                    game_id,
+                    result: GameResult::Draw,
+                });
+            }
+            Tiebreak::Score => {
+                end_game.send(EndGameEvent {
+                    game_id,
+                    result: scores.result(),
+                });
+            }
+            Tiebreak::SuddenDeath if !matches!(scores.result(), GameResult::Draw) => {
+                end_game.send(EndGameEvent {
+                    game_id,
+                    result: scores.result(),
+                });
+            }
+            Tiebreak::SuddenDeath => {
+                timer.sudden_death = Some((Instant::now(), scores.clone()));
+                timer.shown = settings.sudden_death;
+                title.0 = time_text(settings.sudden_death, true);
+                progress.0 = 1.0;
+                style.color = BossBarColor::Red;
+                for entity in entities.0.iter() {
+                    if let Ok((mut client, mut health)) = clients.get_mut(*entity) {
+                        health.0 = health.0.min(1.0);
+                        client.set_title("Sudden Death!".color(Color::RED));
+                        client.send_chat_message(
+                            "Time is up! Sudden death: the next point wins.".color(Color::RED),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn remove_boss_bars(
+    games: Query<&TimeLimit>,
+    mut end_game: EventReader<EndGameEvent>,
+    mut commands: Commands,
+) {
+    for event in end_game.read() {
+        if let Ok(timer) = games.get(event.game_id)
+            && let Some(mut boss_bar) = commands.get_entity(timer.boss_bar)
+        {
+            boss_bar.insert(Despawned);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranking(scores: &TeamScores) -> Option<Vec<u8>> {
+        match scores.result() {
+            GameResult::Ranking(teams) => Some(teams),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn leading_team_wins() {
+        assert_eq!(ranking(&TeamScores(vec![3])), Some(vec![0]));
+        assert_eq!(ranking(&TeamScores(vec![0, 3])), Some(vec![1, 0]));
+        assert_eq!(ranking(&TeamScores(vec![3, 0])), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn added_points_rank_teams() {
+        let mut scores = TeamScores::new(2);
+        scores.add(0, 3);
+        assert_eq!(ranking(&scores), Some(vec![0, 1]));
+        scores.add(1, 4);
+        assert_eq!(ranking(&scores), Some(vec![1, 0]));
+    }
+
+    #[test]
+    fn tied_scores_are_a_draw() {
+        assert_eq!(ranking(&TeamScores::default()), None);
+        assert_eq!(ranking(&TeamScores(vec![0])), None);
+        assert_eq!(ranking(&TeamScores::new(2)), None);
+        assert_eq!(ranking(&TeamScores(vec![2, 2, 1])), None);
+    }
+}
@@ -0,0 +1,324 @@
+#![allow(clippy::type_complexity)]
+
+use super::{DuelsConfig, Match, MatchmakingSet, ServerGlobals, matchmaking::record_waits};
+use crate::config::{DataPath, WorldValue, load_section};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Instant;
+use valence::{
+    command::{AddCommand, handler::CommandResultEvent},
+    command_macros::Command,
+    inventory::ClickSlotEvent,
+    nbt::compound,
+    prelude::*,
+};
+
+const MAX_OPTIONS: usize = 5;
+
+#[derive(Resource, Deserialize)]
+#[serde(default)]
+pub struct MapVoteSettings {
+    /// Seconds players get to vote before the match starts. The vote ends early once everyone
+    /// has voted.
+    pub vote_secs: u64,
+}
+
+impl Default for MapVoteSettings {
+    fn default() -> Self {
+        Self { vote_secs: 5 }
+    }
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("vote")]
+#[scopes("minibit.commands.all.vote")]
+struct VoteCommand {}
+
+/// Remembers the last map played so the same map isn't picked twice in a row.
+#[derive(Resource, Default)]
+pub struct MapRotation {
+    pub last: Option<usize>,
+}
+
+impl MapRotation {
+    /// Maps that can come up next. The first world is the waiting area.
+    pub fn candidates(&self, worlds: &[WorldValue]) -> Vec<usize> {
+        let maps: Vec<usize> = (1..worlds.len())
+            .filter(|&map| Some(map) != self.last)
+            .collect();
+        if maps.is_empty() {
+            (1..worlds.len()).collect()
+        } else {
+            maps
+        }
+    }
+
+    pub fn pick(&self, worlds: &[WorldValue]) -> usize {
+        pick_weighted(worlds, &self.candidates(worlds))
+    }
+}
+
+fn pick_weighted(worlds: &[WorldValue], maps: &[usize]) -> usize {
+    let total: f64 = maps.iter().map(|&map| worlds[map].weight()).sum();
+    let mut roll = fastrand::f64() * total;
+    for &map in maps {
+        roll -= worlds[map].weight();
+        if roll < 0.0 {
+            return map;
+        }
+    }
+    *maps.last().unwrap()
+}
+
+struct MapVote {
+    players: Vec<Entity>,
+    ranked: bool,
+    options: Vec<usize>,
+    /// Index into `options` each player voted for.
+    votes: HashMap<Entity, usize>,
+    inventory: Entity,
+    started: Instant,
+}
+
+#[derive(Resource, Default)]
+struct MapVotes(Vec<MapVote>);
+
+pub struct MapVotePlugin<T: Resource + DuelsConfig> {
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Resource + DuelsConfig + Sync + Send + 'static> Plugin for MapVotePlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_command::<VoteCommand>()
+            .init_resource::<MapRotation>()
+            .init_resource::<MapVotes>()
+            .add_systems(Startup, setup)
+            .add_systems(Update, (handle_vote_command, tick_votes::<T>))
+            .add_systems(EventLoopUpdate, handle_vote_click::<T>)
+            .add_systems(
+                PostUpdate,
+                start_votes::<T>.in_set(MatchmakingSet).after(record_waits),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    commands.insert_resource(load_section::<MapVoteSettings>(&data_path.0, "map_vote"));
+}
+
+/// Holds back new matches without a map until their players have voted for one.
+fn start_votes<T: Resource + DuelsConfig>(
+    mut clients: Query<&mut Client>,
+    mut commands: Commands,
+    mut globals: ResMut<ServerGlobals>,
+    mut votes: ResMut<MapVotes>,
+    rotation: Res<MapRotation>,
+    config: Res<T>,
+) {
+    for game in std::mem::take(&mut globals.matches) {
+        let mut candidates = rotation.candidates(config.worlds());
+        if game.map.is_some() || candidates.len() < 2 {
+            globals.matches.push(game);
+            continue;
+        }
+
+        let mut options = Vec::new();
+        while options.len() < MAX_OPTIONS && !candidates.is_empty() {
+            let map = pick_weighted(config.worlds(), &candidates);
+            candidates.retain(|&candidate| candidate != map);
+            options.push(map);
+        }
+        let mut inventory = Inventory::with_title(InventoryKind::Generic9x1, "Vote for a map");
+        inventory.readonly = true;
+        update_menu(&mut inventory, &options, &HashMap::new(), config.worlds());
+        let vote = MapVote {
+            players: game.players,
+            ranked: game.ranked,
+            options,
+            votes: HashMap::new(),
+            inventory: commands.spawn(inventory).id(),
+            started: Instant::now(),
+        };
+
+        for entity in vote.players.iter() {
+            commands
+                .entity(*entity)
+                .insert(OpenInventory::new(vote.inventory));
+            if let Ok(mut client) = clients.get_mut(*entity) {
+                client.send_chat_message(
+                    Text::from("Match found! Vote for a map. ").color(Color::GREEN)
+                        + Text::from("[Vote]")
+                            .color(Color::AQUA)
+                            .on_click_run_command("/vote"),
+                );
+            }
+        }
+        votes.0.push(vote);
+    }
+}
+
+fn count_votes(votes: &HashMap<Entity, usize>, option: usize) -> usize {
+    votes.values().filter(|&&vote| vote == option).count()
+}
+
+fn update_menu(
+    inventory: &mut Inventory,
+    options: &[usize],
+    votes: &HashMap<Entity, usize>,
+    worlds: &[WorldValue],
+) {
+    for (i, map) in options.iter().enumerate() {
+        let count = count_votes(votes, i);
+        inventory.set_slot(
+            (i * 2) as u16,
+            ItemStack::new(
+                ItemKind::FilledMap,
+                count.max(1) as i8,
+                Some(compound! {
+                    "display" => compound! {
+                        "Name" => format!(
+                            "{{\"text\":\"{} ({} {})\",\"italic\":false}}",
+                            worlds[*map].name(),
+                            count,
+                            if count == 1 { "vote" } else { "votes" }
+                        )
+                    },
+                }),
+            ),
+        );
+    }
+}
+
+fn handle_vote_command(
+    mut events: EventReader<CommandResultEvent<VoteCommand>>,
+    mut clients: Query<&mut Client>,
+    mut commands: Commands,
+    votes: Res<MapVotes>,
+) {
+    for event in events.read() {
+        match votes
+            .0
+            .iter()
+            .find(|vote| vote.players.contains(&event.executor))
+        {
+            Some(vote) => {
+                commands
+                    .entity(event.executor)
+                    .insert(OpenInventory::new(vote.inventory));
+            }
+            None => {
+                if let Ok(mut client) = clients.get_mut(event.executor) {
+                    client.send_chat_message("There is no map vote running!".color(Color::RED));
+                }
+            }
+        }
+    }
+}
+
+fn handle_vote_click<T: Resource + DuelsConfig>(
+    mut inventories: Query<&mut Inventory, Without<Client>>,
+    mut click_slot: EventReader<ClickSlotEvent>,
+    mut votes: ResMut<MapVotes>,
+    config: Res<T>,
+) {
+    for event in click_slot.read() {
+        if event.window_id == 0 || event.slot_id < 0 || event.slot_id % 2 != 0 {
+            continue;
+        }
+        let Some(vote) = votes
+            .0
+            .iter_mut()
+            .find(|vote| vote.players.contains(&event.client))
+        else {
+            continue;
+        };
+        let option = event.slot_id as usize / 2;
+        if option >= vote.options.len() {
+            continue;
+        }
+        vote.votes.insert(event.client, option);
+
+        if let Ok(mut inventory) = inventories.get_mut(vote.inventory) {
+            update_menu(&mut inventory, &vote.options, &vote.votes, config.worlds());
+        }
+    }
+}
+
+fn tick_votes<T: Resource + DuelsConfig>(
+    mut clients: Query<&mut Client>,
+    mut commands: Commands,
+    mut globals: ResMut<ServerGlobals>,
+    mut votes: ResMut<MapVotes>,
+    settings: Res<MapVoteSettings>,
+    config: Res<T>,
+) {
+    let mut i = 0;
+    while i < votes.0.len() {
+        let vote = &votes.0[i];
+        let left = vote.players.iter().any(|entity| !clients.contains(*entity));
+        let elapsed = vote.started.elapsed().as_secs();
+        if !left && elapsed < settings.vote_secs && vote.votes.len() < vote.players.len() {
+            for entity in vote.players.iter() {
+                if let Ok(mut client) = clients.get_mut(*entity) {
+                    client.set_action_bar(
+                        format!("Map vote ends in {}s", settings.vote_secs - elapsed)
+                            .color(Color::YELLOW),
+                    );
+                }
+            }
+            i += 1;
+            continue;
+        }
+
+        let vote = votes.0.remove(i);
+        commands.entity(vote.inventory).insert(Despawned);
+        for entity in vote.players.iter() {
+            if let Some(mut entity) = commands.get_entity(*entity) {
+                entity.remove::<OpenInventory>();
+            }
+        }
+
+        if left {
+            for entity in vote.players.iter() {
+                if let Ok(mut client) = clients.get_mut(*entity) {
+                    client.send_chat_message(
+                        "A player left during the map vote, returning to the queue."
+                            .color(Color::RED),
+                    );
+                    globals.queue.push(*entity);
+                }
+            }
+            continue;
+        }
+
+        // Ties are broken randomly, and nobody voting falls back to the usual rotation
+        let most = (0..vote.options.len())
+            .map(|option| count_votes(&vote.votes, option))
+            .max()
+            .unwrap_or(0);
+        let map = if most == 0 {
+            pick_weighted(config.worlds(), &vote.options)
+        } else {
+            let tied: Vec<usize> = (0..vote.options.len())
+                .filter(|&option| count_votes(&vote.votes, option) == most)
+                .map(|option| vote.options[option])
+                .collect();
+            tied[fastrand::usize(..tied.len())]
+        };
+
+        for entity in vote.players.iter() {
+            if let Ok(mut client) = clients.get_mut(*entity) {
+                client.send_chat_message(
+                    (String::from("Playing on ") + config.worlds()[map].name() + "!")
+                        .color(Color::GREEN),
+                );
+            }
+        }
+        globals.matches.push(Match {
+            players: vote.players,
+            map: Some(map),
+            ranked: vote.ranked,
+        });
+    }
+}
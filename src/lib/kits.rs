@@ -0,0 +1,132 @@
+use crate::color::ArmorColors;
+use crate::config::{DataPath, load_section};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use valence::{
+    nbt::{Compound, List, compound},
+    prelude::*,
+};
+
+/// Replaced by the player's team colour in item names and armour colours.
+const TEAM_PLACEHOLDER: &str = "{team}";
+
+#[derive(Deserialize, Clone)]
+pub struct KitItem {
+    /// Inventory slot, e.g. 5-8 for armour and 36-44 for the hotbar.
+    pub slot: u16,
+    /// Item name like `diamond_pickaxe` or `{team}_terracotta`.
+    pub item: String,
+    #[serde(default = "default_count")]
+    pub count: i8,
+    /// Enchantment levels by name, e.g. `{"efficiency": 2}`.
+    #[serde(default)]
+    pub enchantments: BTreeMap<String, i16>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub unbreakable: bool,
+    /// Leather armour colour, either a dye colour like `red` or `{team}`.
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+fn default_count() -> i8 {
+    1
+}
+
+/// Kits by name, loaded from the `kits` section of `config.json`.
+#[derive(Resource, Deserialize, Default)]
+pub struct Kits(pub HashMap<String, Vec<KitItem>>);
+
+pub fn team_color(team: u8) -> &'static str {
+    match team {
+        0 => "blue",
+        1 => "red",
+        _ => "white",
+    }
+}
+
+impl KitItem {
+    pub fn stack(&self, team: u8) -> Option<ItemStack> {
+        let kind = ItemKind::from_str(&self.item.replace(TEAM_PLACEHOLDER, team_color(team)))?;
+
+        let mut display = Compound::new();
+        if let Some(name) = &self.name {
+            display.insert(
+                "Name",
+                serde_json::json!({ "text": name, "italic": false }).to_string(),
+            );
+        }
+        if let Some(color) = &self.color {
+            let color = ArmorColors::from_name(&color.replace(TEAM_PLACEHOLDER, team_color(team)))?;
+            display.insert("color", color as i32);
+        }
+
+        let mut nbt = Compound::new();
+        if !display.is_empty() {
+            nbt.insert("display", display);
+        }
+        if !self.enchantments.is_empty() {
+            nbt.insert(
+                "Enchantments",
+                List::Compound(
+                    self.enchantments
+                        .iter()
+                        .map(|(id, lvl)| {
+                            compound! {
+                                "id" => id.clone(),
+                                "lvl" => *lvl,
+                            }
+                        })
+                        .collect(),
+                ),
+            );
+        }
+        if self.unbreakable {
+            nbt.insert("Unbreakable", 1_i8);
+        }
+        Some(ItemStack::new(
+            kind,
+            self.count,
+            (!nbt.is_empty()).then_some(nbt),
+        ))
+    }
+}
+
+impl Kits {
+    /// Puts the kit's items into their slots, leaving the rest of the inventory as it is.
+    pub fn give(&self, name: &str, inventory: &mut Inventory, team: u8) {
+        let Some(kit) = self.0.get(name) else {
+            return;
+        };
+        for item in kit.iter() {
+            if let Some(stack) = item.stack(team) {
+                inventory.set_slot(item.slot, stack);
+            }
+        }
+    }
+}
+
+pub struct KitPlugin;
+
+impl Plugin for KitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup);
+    }
+}
+
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    let kits = load_section::<Kits>(&data_path.0, "kits");
+    // Catch typos at startup instead of silently handing out incomplete kits
+    for (name, kit) in kits.0.iter() {
+        for item in kit.iter() {
+            assert!(
+                item.stack(0).is_some(),
+                "Invalid item {} in kit {}",
+                item.item,
+                name
+            );
+        }
+    }
+    commands.insert_resource(kits);
+}
@@ -0,0 +1,238 @@
+use crate::network::{self, NetworkEvent, NetworkMessage};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use valence::{
+    command::{AddCommand, handler::CommandResultEvent, parsers::GreedyString},
+    command_macros::Command,
+    prelude::*,
+};
+
+// Kept outside of the app so the settings follow players between subservers
+static PREFERENCES: LazyLock<Mutex<HashMap<String, Preferences>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Default)]
+struct Preferences {
+    reply_to: Option<String>,
+    blocking: bool,
+    spying: bool,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths(
+    "msg {target} {message}",
+    "tell {target} {message}",
+    "w {target} {message}",
+    "whisper {target} {message}"
+)]
+#[scopes("minibit.commands.all.msg")]
+struct MsgCommand {
+    target: String,
+    message: GreedyString,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("r {message}", "reply {message}")]
+#[scopes("minibit.commands.all.msg")]
+struct ReplyCommand {
+    message: GreedyString,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("msgtoggle", "togglemsg")]
+#[scopes("minibit.commands.all.msgtoggle")]
+struct MsgToggleCommand {}
+
+#[derive(Command, Debug, Clone)]
+#[paths("socialspy")]
+#[scopes("minibit.commands.staff.socialspy")]
+struct SocialSpyCommand {}
+
+pub struct MessagingPlugin;
+
+impl Plugin for MessagingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command::<MsgCommand>()
+            .add_command::<ReplyCommand>()
+            .add_command::<MsgToggleCommand>()
+            .add_command::<SocialSpyCommand>()
+            .add_systems(
+                Update,
+                (
+                    handle_msg_command,
+                    handle_reply_command,
+                    handle_toggle_command,
+                    handle_socialspy_command,
+                    deliver_messages,
+                ),
+            );
+    }
+}
+
+fn handle_msg_command(
+    mut events: EventReader<CommandResultEvent<MsgCommand>>,
+    mut clients: Query<(&mut Client, &Username)>,
+) {
+    for event in events.read() {
+        if let Ok((mut client, username)) = clients.get_mut(event.executor) {
+            send_private(
+                &mut client,
+                &username.0,
+                &event.result.target,
+                &event.result.message.0,
+            );
+        }
+    }
+}
+
+fn handle_reply_command(
+    mut events: EventReader<CommandResultEvent<ReplyCommand>>,
+    mut clients: Query<(&mut Client, &Username)>,
+) {
+    for event in events.read() {
+        let Ok((mut client, username)) = clients.get_mut(event.executor) else {
+            continue;
+        };
+        let reply_to = PREFERENCES
+            .lock()
+            .unwrap()
+            .get(&username.0.to_lowercase())
+            .and_then(|prefs| prefs.reply_to.clone());
+        match reply_to {
+            Some(target) => {
+                send_private(&mut client, &username.0, &target, &event.result.message.0)
+            }
+            None => client.send_chat_message("You have nobody to reply to!".color(Color::RED)),
+        }
+    }
+}
+
+fn handle_toggle_command(
+    mut events: EventReader<CommandResultEvent<MsgToggleCommand>>,
+    mut clients: Query<(&mut Client, &Username)>,
+) {
+    for event in events.read() {
+        if let Ok((mut client, username)) = clients.get_mut(event.executor) {
+            let mut prefs = PREFERENCES.lock().unwrap();
+            let prefs = prefs.entry(username.0.to_lowercase()).or_default();
+            prefs.blocking = !prefs.blocking;
+            client.send_chat_message(if prefs.blocking {
+                "You are no longer accepting private messages.".color(Color::RED)
+            } else {
+                "You are now accepting private messages.".color(Color::GREEN)
+            });
+        }
+    }
+}
+
+fn handle_socialspy_command(
+    mut events: EventReader<CommandResultEvent<SocialSpyCommand>>,
+    mut clients: Query<(&mut Client, &Username)>,
+) {
+    for event in events.read() {
+        if let Ok((mut client, username)) = clients.get_mut(event.executor) {
+            let mut prefs = PREFERENCES.lock().unwrap();
+            let prefs = prefs.entry(username.0.to_lowercase()).or_default();
+            prefs.spying = !prefs.spying;
+            client.send_chat_message(if prefs.spying {
+                "Social spy enabled.".color(Color::GREEN)
+            } else {
+                "Social spy disabled.".color(Color::RED)
+            });
+        }
+    }
+}
+
+fn deliver_messages(
+    mut clients: Query<(&mut Client, &Username)>,
+    mut events: EventReader<NetworkEvent>,
+) {
+    for NetworkEvent(message) in events.read() {
+        match message {
+            NetworkMessage::Private { from, to, message } => {
+                for (mut client, username) in clients.iter_mut() {
+                    if username.0 == *to {
+                        client.send_chat_message(
+                            (Text::from("From ").color(Color::LIGHT_PURPLE)
+                                + Text::from(from.clone()).color(Color::GRAY)
+                                + Text::from(": ").color(Color::LIGHT_PURPLE)
+                                + Text::from(message.clone()).color(Color::GRAY))
+                            .on_click_suggest_command(format!("/msg {} ", from)),
+                        );
+                    }
+                }
+            }
+            NetworkMessage::SocialSpy { from, to, message } => {
+                let prefs = PREFERENCES.lock().unwrap();
+                for (mut client, username) in clients.iter_mut() {
+                    if username.0 == *from
+                        || username.0 == *to
+                        || !prefs
+                            .get(&username.0.to_lowercase())
+                            .is_some_and(|prefs| prefs.spying)
+                    {
+                        continue;
+                    }
+                    client.send_chat_message(
+                        Text::from("[SPY] ").color(Color::DARK_GRAY)
+                            + Text::from(from.clone() + " -> " + to + ": ").color(Color::GRAY)
+                            + Text::from(message.clone()).color(Color::WHITE),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_private(client: &mut Client, from: &str, to: &str, message: &str) {
+    let Some(target) = network::find_player(to) else {
+        client.send_chat_message(
+            (String::from("Player ") + to + " is not online!").color(Color::RED),
+        );
+        return;
+    };
+    if target.username.eq_ignore_ascii_case(from) {
+        client.send_chat_message("You can't message yourself!".color(Color::RED));
+        return;
+    }
+
+    {
+        let mut prefs = PREFERENCES.lock().unwrap();
+        if prefs
+            .get(&target.username.to_lowercase())
+            .is_some_and(|prefs| prefs.blocking)
+        {
+            client.send_chat_message(
+                (target.username + " is not accepting private messages.").color(Color::RED),
+            );
+            return;
+        }
+        prefs.entry(from.to_lowercase()).or_default().reply_to = Some(target.username.clone());
+        prefs
+            .entry(target.username.to_lowercase())
+            .or_default()
+            .reply_to = Some(from.to_string());
+    }
+
+    client.send_chat_message(
+        Text::from("To ").color(Color::LIGHT_PURPLE)
+            + Text::from(target.username.clone()).color(Color::GRAY)
+            + Text::from(": ").color(Color::LIGHT_PURPLE)
+            + Text::from(message.to_string()).color(Color::GRAY),
+    );
+
+    network::send(
+        &target.server,
+        NetworkMessage::Private {
+            from: from.to_string(),
+            to: target.username.clone(),
+            message: message.to_string(),
+        },
+    );
+    network::broadcast(NetworkMessage::SocialSpy {
+        from: from.to_string(),
+        to: target.username,
+        message: message.to_string(),
+    });
+}
@@ -1,12 +1,21 @@
+pub mod anticheat;
+pub mod chat;
 pub mod color;
+pub mod combat;
 pub mod config;
 pub mod damage;
 pub mod db;
 pub mod death;
 pub mod duels;
 pub mod food;
+pub mod kits;
+pub mod messaging;
+pub mod network;
 pub mod player;
+pub mod profile;
 pub mod projectiles;
+pub mod reports;
 pub mod scopes;
 pub mod scoreboard;
+pub mod throwables;
 pub mod world;
@@ -0,0 +1,147 @@
+#![allow(dead_code)]
+
+use crate::config::ServerName;
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{LazyLock, Mutex};
+use valence::prelude::*;
+
+// Every subserver runs in its own thread of the same process, so they share this directory to
+// find players and pass messages to each other.
+static DIRECTORY: LazyLock<Mutex<Directory>> = LazyLock::new(|| Mutex::new(Directory::default()));
+
+#[derive(Default)]
+struct Directory {
+    players: HashMap<String, OnlinePlayer>,
+    inboxes: HashMap<String, Sender<NetworkMessage>>,
+}
+
+#[derive(Clone)]
+pub struct OnlinePlayer {
+    pub username: String,
+    pub server: String,
+}
+
+#[derive(Clone)]
+pub enum NetworkMessage {
+    Private {
+        from: String,
+        to: String,
+        message: String,
+    },
+    SocialSpy {
+        from: String,
+        to: String,
+        message: String,
+    },
+    Report {
+        id: i32,
+        reporter: String,
+        target: String,
+        reason: String,
+        server: String,
+    },
+    Violation {
+        player: String,
+        check: String,
+        details: String,
+        level: f32,
+        server: String,
+    },
+}
+
+#[derive(Event)]
+pub struct NetworkEvent(pub NetworkMessage);
+
+#[derive(Resource)]
+struct Inbox(Mutex<Receiver<NetworkMessage>>);
+
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<NetworkEvent>()
+            .add_systems(Startup, setup)
+            .add_systems(Update, (register_clients, unregister_clients, read_inbox));
+    }
+}
+
+fn setup(mut commands: Commands, server_name: Res<ServerName>) {
+    let (sender, receiver) = channel();
+    DIRECTORY
+        .lock()
+        .unwrap()
+        .inboxes
+        .insert(server_name.0.clone(), sender);
+    commands.insert_resource(Inbox(Mutex::new(receiver)));
+}
+
+fn register_clients(clients: Query<&Username, Added<Client>>, server_name: Res<ServerName>) {
+    let mut directory = DIRECTORY.lock().unwrap();
+    for username in clients.iter() {
+        directory.players.insert(
+            username.0.to_lowercase(),
+            OnlinePlayer {
+                username: username.0.clone(),
+                server: server_name.0.clone(),
+            },
+        );
+    }
+}
+
+fn unregister_clients(clients: Query<&Username, Added<Despawned>>, server_name: Res<ServerName>) {
+    let mut directory = DIRECTORY.lock().unwrap();
+    for username in clients.iter() {
+        // The proxy connects players to their new subserver before leaving the old one
+        let key = username.0.to_lowercase();
+        if directory
+            .players
+            .get(&key)
+            .is_some_and(|player| player.server == server_name.0)
+        {
+            directory.players.remove(&key);
+        }
+    }
+}
+
+fn read_inbox(inbox: Res<Inbox>, mut events: EventWriter<NetworkEvent>) {
+    let receiver = inbox.0.lock().unwrap();
+    while let Ok(message) = receiver.try_recv() {
+        events.send(NetworkEvent(message));
+    }
+}
+
+pub fn find_player(username: &str) -> Option<OnlinePlayer> {
+    DIRECTORY
+        .lock()
+        .unwrap()
+        .players
+        .get(&username.to_lowercase())
+        .cloned()
+}
+
+pub fn send(server: &str, message: NetworkMessage) {
+    if let Some(inbox) = DIRECTORY.lock().unwrap().inboxes.get(server) {
+        let _ = inbox.send(message);
+    }
+}
+
+pub fn broadcast(message: NetworkMessage) {
+    for inbox in DIRECTORY.lock().unwrap().inboxes.values() {
+        let _ = inbox.send(message.clone());
+    }
+}
+
+/// The subserver players return to when they leave a game.
+pub const LOBBY: &str = "lobby";
+
+/// Asks the proxy to move the player to another subserver.
+pub fn warp(client: &mut Client, username: &str, server: &str) {
+    let mut payload: Vec<u8> = Vec::new();
+    payload.extend_from_slice("1".as_bytes());
+    payload.push(0);
+    payload.extend_from_slice(username.as_bytes());
+    payload.push(0);
+    payload.extend_from_slice(server.as_bytes());
+    client.send_custom_payload(ident!("minibit:main"), &payload);
+}
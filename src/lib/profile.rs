@@ -0,0 +1,148 @@
+use crate::db::Database;
+use crate::db::models::{Guild, Player, Rank};
+use crate::db::schema::{guilds, players, rank_permissions, ranks};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use valence::log::warn;
+use valence::{command::scopes::CommandScopes, prelude::*};
+
+/// Rank, guild and level information loaded from the database when a player joins. Players get the
+/// default profile if the server is running without a database or the query fails.
+#[derive(Component, Clone)]
+pub struct PlayerProfile {
+    pub rank_prefix: String,
+    pub rank_color: String,
+    pub guild_tag: Option<String>,
+    pub level: i32,
+}
+
+impl Default for PlayerProfile {
+    fn default() -> Self {
+        Self {
+            rank_prefix: String::new(),
+            rank_color: "7".to_string(),
+            guild_tag: None,
+            level: 1,
+        }
+    }
+}
+
+pub struct ProfilePlugin;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+        app.insert_resource(LoadedProfiles {
+            sender,
+            receiver: Mutex::new(receiver),
+        })
+        .add_systems(Update, (load_profiles, insert_profiles));
+    }
+}
+
+/// Profiles are queried on a separate thread so that joining doesn't stall the tick.
+#[derive(Resource)]
+struct LoadedProfiles {
+    sender: Sender<(Entity, LoadedProfile)>,
+    receiver: Mutex<Receiver<(Entity, LoadedProfile)>>,
+}
+
+struct LoadedProfile {
+    profile: PlayerProfile,
+    permissions: Vec<String>,
+}
+
+fn load_profiles(
+    clients: Query<(Entity, &UniqueId), Added<Client>>,
+    mut commands: Commands,
+    loaded: Res<LoadedProfiles>,
+    db: Option<Res<Database>>,
+) {
+    for (entity, uuid) in clients.iter() {
+        // Everyone starts with the default profile until the database responds
+        commands.entity(entity).insert(PlayerProfile::default());
+        let Some(db) = &db else {
+            continue;
+        };
+        let conn = db.0.clone();
+        let sender = loaded.sender.clone();
+        let uuid = uuid.0.as_u128();
+        thread::spawn(move || match query_profile(&conn, uuid) {
+            Ok(profile) => {
+                let _ = sender.send((entity, profile));
+            }
+            Err(err) => warn!("Failed to load profile of {uuid:x}: {err}"),
+        });
+    }
+}
+
+fn insert_profiles(
+    mut clients: Query<Option<&mut CommandScopes>, With<Client>>,
+    mut commands: Commands,
+    loaded: Res<LoadedProfiles>,
+) {
+    let Ok(receiver) = loaded.receiver.lock() else {
+        return;
+    };
+    for (entity, loaded) in receiver.try_iter() {
+        // The player may have left while their profile was loading
+        let Ok(scopes) = clients.get_mut(entity) else {
+            continue;
+        };
+        if let Some(mut scopes) = scopes {
+            for permission in loaded.permissions.iter() {
+                scopes.add(permission);
+            }
+        }
+        commands.entity(entity).insert(loaded.profile);
+    }
+}
+
+fn query_profile(conn: &Mutex<PgConnection>, uuid: u128) -> Result<LoadedProfile, Box<dyn Error>> {
+    let mut conn = conn.lock().map_err(|_| "database connection poisoned")?;
+    let id = BigDecimal::from_str(&uuid.to_string())?;
+
+    diesel::insert_into(players::table)
+        .values(players::uuid.eq(&id))
+        .on_conflict(players::uuid)
+        .do_update()
+        .set(players::last_login.eq(diesel::dsl::now))
+        .execute(&mut *conn)?;
+
+    let (player, rank, guild) = players::table
+        .left_join(ranks::table)
+        .left_join(guilds::table)
+        .filter(players::uuid.eq(&id))
+        .select((
+            Player::as_select(),
+            ranks::all_columns.nullable(),
+            guilds::all_columns.nullable(),
+        ))
+        .first::<(Player, Option<Rank>, Option<Guild>)>(&mut *conn)?;
+
+    let permissions = match &rank {
+        Some(rank) => rank_permissions::table
+            .filter(rank_permissions::rank_id.eq(rank.id))
+            .select(rank_permissions::permission)
+            .load::<String>(&mut *conn)?,
+        None => Vec::new(),
+    };
+
+    let default = PlayerProfile::default();
+    Ok(LoadedProfile {
+        profile: PlayerProfile {
+            rank_prefix: rank
+                .as_ref()
+                .map_or(default.rank_prefix, |rank| rank.prefix.clone()),
+            rank_color: rank.map_or(default.rank_color, |rank| rank.color),
+            guild_tag: guild.map(|guild| guild.tag),
+            level: player.level,
+        },
+        permissions,
+    })
+}
@@ -1,6 +1,10 @@
 #![allow(dead_code)]
 #![allow(clippy::type_complexity)]
 
+use crate::config::{DataPath, load_section};
+use crate::damage::{DamageType, arrow_base_dmg, enchantment_knockback, enchantment_level};
+use crate::death::DeathEvent;
+use crate::duels::PlayerGameState;
 use bevy_ecs::query::QueryData;
 use parry3d::{
     math::Vector,
@@ -8,11 +12,14 @@ use parry3d::{
     query::{ShapeCastOptions, cast_shapes},
     shape::Cuboid,
 };
+use serde::Deserialize;
 use valence::inventory::player_inventory::PlayerInventory;
 use valence::{
     entity::{
         Velocity,
         arrow::{ArrowEntity, ArrowEntityBundle},
+        entity::Flags,
+        living::Health,
     },
     event_loop::PacketEvent,
     interact_item::InteractItemEvent,
@@ -27,22 +34,134 @@ struct BowDrawTick(pub i64, pub Hand);
 #[derive(Component)]
 pub struct ProjectileOwner(pub Entity);
 
+/// Marks an arrow shot at full draw, which deals bonus damage.
+#[derive(Component)]
+pub struct CriticalArrow;
+
+/// Enchantments of the bow an arrow was shot from.
+#[derive(Component, Clone, Copy, Default)]
+pub struct ArrowEnchantments {
+    pub power: i16,
+    pub punch: i16,
+    pub flame: bool,
+    /// Infinity arrows aren't taken from the inventory and can't be picked up.
+    pub infinity: bool,
+}
+
+/// Set on players hit by a Flame arrow, who take half a heart every second until the given tick.
+#[derive(Component)]
+pub struct Burning {
+    pub until: i64,
+}
+
+/// The chunk layer a projectile collides with, taken from its shooter.
+#[derive(Component)]
+pub struct ProjectileLayer(pub Entity);
+
+/// An arrow stuck in a block since the given tick.
+#[derive(Component)]
+pub struct StuckArrow {
+    pub block: BlockPos,
+    pub since: i64,
+}
+
+/// Shots weaker than this are cancelled, which takes about 3 ticks of drawing.
+const MIN_BOW_POWER: f32 = 0.1;
+/// Arrow speed in blocks per second at full power.
+const ARROW_SPEED: f32 = 60.0;
+/// Standard deviation of the random spread added to each component of a projectile's direction.
+const ARROW_SPREAD: f32 = 0.0075;
+const EYE_HEIGHT: f64 = 1.62;
+/// Eye height of a sneaking 1.20 client, which is what players aim with.
+const SNEAKING_EYE_HEIGHT: f64 = 1.27;
+/// Half the size of an arrow's hitbox, in blocks.
+const ARROW_SIZE: f32 = 0.5;
+/// Ticks a Flame arrow sets its victim on fire for.
+const FLAME_TICKS: i64 = 100;
+
+/// Who can pick up an arrow once it's stuck in a block.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArrowPickup {
+    Owner,
+    Anyone,
+    Nobody,
+}
+
+#[derive(Resource, Deserialize)]
+#[serde(default)]
+pub struct ArrowSettings {
+    pub pickup: ArrowPickup,
+    /// Ticks an arrow has to be stuck for before it can be picked up.
+    pub pickup_delay: i64,
+    /// Ticks until a stuck arrow despawns.
+    pub despawn_ticks: i64,
+}
+
+impl Default for ArrowSettings {
+    fn default() -> Self {
+        Self {
+            pickup: ArrowPickup::Owner,
+            pickup_delay: 10,
+            despawn_ticks: 1200,
+        }
+    }
+}
+
+/// Sent for every arrow shot from a bow.
 #[derive(Event)]
-pub struct ProjectileCollisionEvent {
+pub struct BowShootEvent {
+    pub shooter: Entity,
     pub arrow: Entity,
+    /// Draw strength from 0.1 to 1, where 1 is a critical arrow.
+    pub power: f32,
+    /// The slot the arrow was taken from, or `None` with Infinity.
+    pub arrow_slot: Option<u16>,
+}
+
+/// Sent when a projectile hits a player. `projectile` is an arrow or a thrown item.
+#[derive(Event)]
+pub struct ProjectileCollisionEvent {
+    pub projectile: Entity,
     pub player: Entity,
 }
 
+/// Sent when a projectile hits a block.
+#[derive(Event)]
+pub struct ProjectileBlockHitEvent {
+    pub projectile: Entity,
+    pub block: BlockPos,
+}
+
 pub struct ProjectilePlugin;
 
 impl Plugin for ProjectilePlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<ProjectileCollisionEvent>()
+        app.add_event::<BowShootEvent>()
+            .add_event::<ProjectileCollisionEvent>()
+            .add_event::<ProjectileBlockHitEvent>()
+            .add_event::<DeathEvent>()
+            .add_systems(Startup, setup)
             .add_systems(EventLoopUpdate, (set_use_tick, handle_player_actions))
-            .add_systems(Update, (init_clients, apply_arrow_physics, cleanup_arrows));
+            .add_systems(
+                Update,
+                (
+                    init_clients,
+                    apply_arrow_physics,
+                    release_arrows,
+                    pick_up_arrows,
+                    cleanup_arrows,
+                    burn_players,
+                    extinguish_players,
+                ),
+            );
     }
 }
 
+fn setup(mut commands: Commands, data_path: Res<DataPath>) {
+    commands.insert_resource(load_section::<ArrowSettings>(&data_path.0, "arrows"));
+}
+
 fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
     for entity in clients.iter() {
         commands
@@ -82,152 +201,489 @@ struct ActionQuery {
     look: &'static Look,
     yaw: &'static HeadYaw,
     layer: &'static EntityLayerId,
+    chunk_layer: &'static VisibleChunkLayer,
+    flags: &'static Flags,
     draw_tick: &'static mut BowDrawTick,
 }
+
+/// 1.8 bow strength after drawing for `ticks`, from 0 to 1.
+pub fn bow_power(ticks: i64) -> f32 {
+    let secs = ticks.max(0) as f32 / 20.0;
+    ((secs * secs + secs * 2.0) / 3.0).min(1.0)
+}
+
+/// A normally distributed random number with a standard deviation of 1.
+fn gaussian() -> f32 {
+    let u = fastrand::f32().max(f32::MIN_POSITIVE);
+    (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * fastrand::f32()).cos()
+}
+
+/// The direction a player is looking in, with the random spread every projectile gets.
+pub(crate) fn launch_direction(yaw: f32, pitch: f32) -> Vec3 {
+    let rad_yaw = yaw.to_radians();
+    let rad_pitch = pitch.to_radians();
+    let hspeed = rad_pitch.cos();
+    let dir = Vec3::new(
+        -rad_yaw.sin() * hspeed,
+        -rad_pitch.sin(),
+        rad_yaw.cos() * hspeed,
+    );
+    (dir.normalize() + Vec3::new(gaussian(), gaussian(), gaussian()) * ARROW_SPREAD).normalize()
+}
+
+/// Where projectiles leave from: slightly below the eyes and to the side of the held item.
+pub(crate) fn launch_origin(pos: DVec3, yaw: f32, flags: &Flags) -> DVec3 {
+    let rad_yaw = yaw.to_radians() as f64;
+    let eye_height = if flags.sneaking() {
+        SNEAKING_EYE_HEIGHT
+    } else {
+        EYE_HEIGHT
+    };
+    DVec3::new(
+        pos.x - rad_yaw.cos() * 0.16,
+        pos.y + eye_height - 0.1,
+        pos.z - rad_yaw.sin() * 0.16,
+    )
+}
+
 fn handle_player_actions(
     mut players: Query<ActionQuery>,
     mut clients: Query<&mut Client>,
     mut packets: EventReader<PacketEvent>,
+    mut shots: EventWriter<BowShootEvent>,
     mut commands: Commands,
     server: Res<Server>,
 ) {
     for packet in packets.read() {
-        if let Some(pkt) = packet.decode::<PlayerActionC2s>() {
-            let Ok(mut player) = players.get_mut(packet.client) else {
-                continue;
-            };
-            let bow_slot = match player.draw_tick.1 {
-                Hand::Main => player.held_item.slot(),
-                Hand::Off => PlayerInventory::SLOT_OFFHAND,
-            };
+        let Some(pkt) = packet.decode::<PlayerActionC2s>() else {
+            continue;
+        };
+        if pkt.action != PlayerAction::ReleaseUseItem {
+            continue;
+        }
+        let Ok(mut player) = players.get_mut(packet.client) else {
+            continue;
+        };
+        let (bow_slot, other_hand) = match player.draw_tick.1 {
+            Hand::Main => (player.held_item.slot(), PlayerInventory::SLOT_OFFHAND),
+            Hand::Off => (PlayerInventory::SLOT_OFFHAND, player.held_item.slot()),
+        };
+        let bow = player.inv.slot(bow_slot);
+        if bow.item != ItemKind::Bow {
+            continue;
+        }
+        let enchantments = ArrowEnchantments {
+            power: enchantment_level(bow, "power"),
+            punch: enchantment_level(bow, "punch"),
+            flame: enchantment_level(bow, "flame") > 0,
+            infinity: enchantment_level(bow, "infinity") > 0,
+        };
 
-            if player.inv.slot(bow_slot).item != ItemKind::Bow {
-                continue;
-            }
-            let Some(arrow_slot) = player.inv.first_slot_with_item(ItemKind::Arrow, 65) else {
-                continue;
-            };
-            let count = player.inv.slot(arrow_slot).count;
-            if count == 0 {
-                continue;
-            }
-            if pkt.action != PlayerAction::ReleaseUseItem {
-                continue;
-            }
+        let power = bow_power(server.current_tick() - player.draw_tick.0);
+        player.draw_tick.0 = i64::MAX;
+        if power < MIN_BOW_POWER {
+            continue;
+        }
 
-            player.inv.set_slot_amount(arrow_slot, count - 1);
-            for mut client in clients.iter_mut() {
-                client.play_sound(
-                    Sound::EntityArrowShoot,
-                    SoundCategory::Player,
-                    player.pos.0,
-                    1.0,
-                    1.0,
-                );
+        // Arrows in the other hand are used before the rest of the inventory
+        let arrow_slot = if player.inv.slot(other_hand).item == ItemKind::Arrow {
+            Some(other_hand)
+        } else {
+            player.inv.first_slot_with_item(ItemKind::Arrow, 65)
+        };
+        let arrow_slot = match arrow_slot {
+            _ if enchantments.infinity => None,
+            Some(slot) if player.inv.slot(slot).count > 0 => {
+                let count = player.inv.slot(slot).count;
+                player.inv.set_slot_amount(slot, count - 1);
+                Some(slot)
             }
+            _ => continue,
+        };
 
-            let rad_yaw = player.yaw.0.to_radians();
-            let rad_pitch = player.look.pitch.to_radians();
-            let hspeed = rad_pitch.cos();
-            let x = -rad_yaw.sin() * hspeed;
-            let y = -rad_pitch.sin();
-            let z = rad_yaw.cos() * hspeed;
-
-            let mag = (x * x + y * y + z * z).sqrt();
-
-            let tick_diff = server.current_tick() - player.draw_tick.0;
-
-            let vel = Vec3::new(x / mag, y / mag, z / mag) * tick_diff.clamp(0, 20) as f32 * 3.0;
-            let dir = vel.normalize().as_dvec3() * 0.5;
-            let arrow_id = commands
-                .spawn(ArrowEntityBundle {
-                    position: Position(DVec3::new(
-                        player.pos.0.x + dir.x,
-                        player.pos.0.y + 1.62,
-                        player.pos.0.z + dir.z,
-                    )),
-                    look: *player.look,
-                    head_yaw: *player.yaw,
-                    velocity: Velocity(vel),
-                    layer: *player.layer,
-                    ..Default::default()
-                })
-                .id();
-            commands
-                .entity(arrow_id)
-                .insert(ProjectileOwner(player.entity));
+        for mut client in clients.iter_mut() {
+            client.play_sound(
+                Sound::EntityArrowShoot,
+                SoundCategory::Player,
+                player.pos.0,
+                1.0,
+                1.0 / (fastrand::f32() * 0.4 + 1.2) + power * 0.5,
+            );
+        }
 
-            player.draw_tick.0 = i64::MAX;
+        let vel = launch_direction(player.yaw.0, player.look.pitch) * power * ARROW_SPEED;
+        let arrow_id = commands
+            .spawn(ArrowEntityBundle {
+                position: Position(launch_origin(player.pos.0, player.yaw.0, player.flags)),
+                look: *player.look,
+                head_yaw: *player.yaw,
+                velocity: Velocity(vel),
+                layer: *player.layer,
+                ..Default::default()
+            })
+            .id();
+        commands.entity(arrow_id).insert((
+            ProjectileOwner(player.entity),
+            ProjectileLayer(player.chunk_layer.0),
+            enchantments,
+        ));
+        if power >= 1.0 {
+            commands.entity(arrow_id).insert(CriticalArrow);
         }
+        if enchantments.flame {
+            let mut flags = Flags::default();
+            flags.set_on_fire(true);
+            commands.entity(arrow_id).insert(flags);
+        }
+
+        shots.send(BowShootEvent {
+            shooter: player.entity,
+            arrow: arrow_id,
+            power,
+            arrow_slot,
+        });
     }
 }
 
 pub fn apply_arrow_physics(
-    mut arrows: Query<(Entity, &mut Position, &mut Velocity), With<ArrowEntity>>,
-    players: Query<(Entity, &Position, &Velocity), (With<Client>, Without<ArrowEntity>)>,
+    mut arrows: Query<
+        (
+            Entity,
+            &mut Position,
+            &mut Velocity,
+            &ProjectileLayer,
+            &ProjectileOwner,
+            &ArrowEnchantments,
+        ),
+        (With<ArrowEntity>, Without<StuckArrow>),
+    >,
+    players: Query<(Entity, &Position), (With<Client>, Without<ArrowEntity>)>,
+    layers: Query<&ChunkLayer>,
+    gamestates: Query<&PlayerGameState>,
     mut collisions: EventWriter<ProjectileCollisionEvent>,
+    mut block_hits: EventWriter<ProjectileBlockHitEvent>,
     mut commands: Commands,
+    server: Res<Server>,
 ) {
-    for (entity, mut pos, mut vel) in arrows.iter_mut() {
-        pos.0 += DVec3::from(vel.0) / 20.0;
+    for (entity, mut pos, mut vel, layer, owner, enchantments) in arrows.iter_mut() {
+        let step = DVec3::from(vel.0) / 20.0;
+        let targets = players
+            .iter()
+            // Arrows start inside their shooter's hitbox
+            .filter(|(player, _)| *player != owner.0)
+            .map(|(player, player_pos)| (player, player_pos.0));
 
-        // Gravity
-        vel.0.y -= 1.0;
+        match sweep_projectile(layers.get(layer.0).ok(), pos.0, step, ARROW_SIZE, targets) {
+            Some((ProjectileHit::Player(player), _)) => {
+                commands.entity(entity).insert(Despawned);
+                // Only set opponents on fire, the same as melee hits only land between them
+                if enchantments.flame
+                    && let Ok([shooter, victim]) = gamestates.get_many([owner.0, player])
+                    && shooter.game_id.is_some()
+                    && shooter.game_id == victim.game_id
+                    && shooter.team != victim.team
+                {
+                    commands.entity(player).insert(Burning {
+                        until: server.current_tick() + FLAME_TICKS,
+                    });
+                }
+                collisions.send(ProjectileCollisionEvent {
+                    projectile: entity,
+                    player,
+                });
+            }
+            Some((ProjectileHit::Block(block), t)) => {
+                // Back off slightly so the arrow sits in the face of the block rather than inside it
+                pos.0 += step * t - step.normalize_or_zero() * 0.05;
+                vel.0 = Vec3::ZERO;
+                commands.entity(entity).insert(StuckArrow {
+                    block,
+                    since: server.current_tick(),
+                });
+                block_hits.send(ProjectileBlockHitEvent {
+                    projectile: entity,
+                    block,
+                });
+            }
+            None => {
+                pos.0 += step;
 
-        // Air resistance
-        vel.0 *= 0.99;
+                // Gravity
+                vel.0.y -= 1.0;
 
-        // Check for collisions (Arrow's have a hitbox of 0.5x0.5x0.5 and players have a hitbox of 0.6x1.8x0.6)
-        let arrow_shape = Cuboid::new(Vector::new(0.5, 0.5, 0.5));
-        let arrow_iso = Isometry3::new(
-            Vector::new(pos.0.x as f32, pos.0.y as f32, pos.0.z as f32),
+                // Air resistance
+                vel.0 *= 0.99;
+            }
+        }
+    }
+}
+
+/// What a projectile runs into.
+#[derive(Clone, Copy)]
+pub(crate) enum ProjectileHit {
+    Player(Entity),
+    Block(BlockPos),
+}
+
+/// Moves a projectile with the given half size along `step` and returns the first player or block
+/// in its way, with how far along `step` it got. Players are given by their feet position.
+pub(crate) fn sweep_projectile(
+    layer: Option<&ChunkLayer>,
+    from: DVec3,
+    step: DVec3,
+    size: f32,
+    players: impl Iterator<Item = (Entity, DVec3)>,
+) -> Option<(ProjectileHit, f64)> {
+    let mut closest = layer
+        .and_then(|layer| raycast_blocks(layer, from, step))
+        .map(|(block, t)| (ProjectileHit::Block(block), t));
+
+    // Players have a hitbox of 0.6x1.8x0.6, which is made more forgiving here
+    let shape = Cuboid::new(Vector::new(size, size, size));
+    let iso = Isometry3::new(
+        Vector::new(from.x as f32, from.y as f32, from.z as f32),
+        na::zero(),
+    );
+    let vel = Vector::new(step.x as f32, step.y as f32, step.z as f32);
+    let player_shape = Cuboid::new(Vector::new(0.6, 0.9, 0.6));
+    for (player, pos) in players {
+        let player_iso = Isometry3::new(
+            Vector::new(pos.x as f32, pos.y as f32 + 0.9, pos.z as f32),
             na::zero(),
         );
-        let arrow_vel = Vector::new(vel.0.x / 100.0, vel.0.y / 100.0, vel.0.z / 100.0);
+        let Ok(Some(hit)) = cast_shapes(
+            &iso,
+            &vel,
+            &shape,
+            &player_iso,
+            &Vector::zeros(),
+            &player_shape,
+            ShapeCastOptions::with_max_time_of_impact(1.0),
+        ) else {
+            continue;
+        };
+        let t = hit.time_of_impact as f64;
+        if closest.is_none_or(|(_, best)| t < best) {
+            closest = Some((ProjectileHit::Player(player), t));
+        }
+    }
+    closest
+}
 
-        let player_shape = Cuboid::new(Vector::new(0.6, 0.9, 0.6));
+/// 1.8 arrow damage before armour: the arrow's speed in blocks per tick times the base damage,
+/// rounded up, plus a random bonus of up to half that again for critical arrows.
+pub fn arrow_damage(vel: Vec3, critical: bool, enchantments: &ArrowEnchantments) -> f32 {
+    let damage = (vel.length() / 20.0 * arrow_base_dmg(enchantments.power)).ceil() as u32;
+    let bonus = if critical {
+        fastrand::u32(0..damage / 2 + 2)
+    } else {
+        0
+    };
+    (damage + bonus) as f32
+}
 
-        for (player_entity, player_pos, player_vel) in players.iter() {
-            let player_iso = Isometry3::new(
-                Vector::new(
-                    player_pos.0.x as f32,
-                    player_pos.0.y as f32 + 0.9,
-                    player_pos.0.z as f32,
-                ),
-                na::zero(),
-            );
-            let player_vel = Vector::new(
-                player_vel.0.x / 100.0,
-                player_vel.0.y / 100.0,
-                player_vel.0.z / 100.0,
-            );
+/// Knockback of an arrow. Like an unsprinted melee hit, but pushing the victim along the arrow's
+/// flight path instead of away from the shooter, with Punch adding to it.
+pub fn arrow_knockback(
+    vel: Vec3,
+    horizontal: f32,
+    vertical: f32,
+    enchantments: &ArrowEnchantments,
+) -> Vec3 {
+    let (bonus_horizontal, bonus_vertical) =
+        enchantment_knockback(enchantments.punch, DamageType::Projectile);
+    let dir = vel.with_y(0.0).normalize_or_zero() * (horizontal + bonus_horizontal);
+    Vec3::new(dir.x, vertical + bonus_vertical, dir.z)
+}
+
+/// The first block whose collision shape the segment from `from` to `from + delta` passes
+/// through, and how far along the segment it enters it.
+fn raycast_blocks(layer: &ChunkLayer, from: DVec3, delta: DVec3) -> Option<(BlockPos, f64)> {
+    let to = from + delta;
+    let min = from.min(to).floor();
+    let max = from.max(to).floor();
+    let mut closest: Option<(f64, BlockPos)> = None;
+    for x in min.x as i32..=max.x as i32 {
+        // Fences and walls stick out of the block below
+        for y in min.y as i32 - 1..=max.y as i32 {
+            for z in min.z as i32..=max.z as i32 {
+                let Some(block) = layer.block([x, y, z]) else {
+                    continue;
+                };
+                let offset = DVec3::new(x as f64, y as f64, z as f64);
+                for aabb in block.state.collision_shapes() {
+                    if let Some(t) =
+                        segment_entry(from, delta, aabb.min() + offset, aabb.max() + offset)
+                        && closest.is_none_or(|(best, _)| t < best)
+                    {
+                        closest = Some((t, BlockPos::new(x, y, z)));
+                    }
+                }
+            }
+        }
+    }
+    closest.map(|(t, block)| (block, t))
+}
 
-            if cast_shapes(
-                &arrow_iso,
-                &arrow_vel,
-                &arrow_shape,
-                &player_iso,
-                &player_vel,
-                &player_shape,
-                ShapeCastOptions::with_max_time_of_impact(1.0),
-            )
-            .unwrap()
-            .is_some()
+/// How far along the segment, from 0 to 1, it enters the box, if it does at all.
+fn segment_entry(from: DVec3, delta: DVec3, min: DVec3, max: DVec3) -> Option<f64> {
+    let mut entry = 0.0_f64;
+    let mut exit = 1.0_f64;
+    for axis in 0..3 {
+        if delta[axis].abs() < f64::EPSILON {
+            if from[axis] < min[axis] || from[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let a = (min[axis] - from[axis]) / delta[axis];
+        let b = (max[axis] - from[axis]) / delta[axis];
+        entry = entry.max(a.min(b));
+        exit = exit.min(a.max(b));
+        if entry > exit {
+            return None;
+        }
+    }
+    Some(entry)
+}
+
+/// Lets arrows fall again once the block they're stuck in is broken.
+fn release_arrows(
+    arrows: Query<(Entity, &StuckArrow, &ProjectileLayer)>,
+    layers: Query<&ChunkLayer>,
+    mut commands: Commands,
+) {
+    for (entity, stuck, layer) in arrows.iter() {
+        let Ok(layer) = layers.get(layer.0) else {
+            continue;
+        };
+        if layer
+            .block(stuck.block)
+            .is_none_or(|block| block.state.collision_shapes().next().is_none())
+        {
+            commands.entity(entity).remove::<StuckArrow>();
+        }
+    }
+}
+
+fn pick_up_arrows(
+    arrows: Query<(
+        Entity,
+        &Position,
+        &EntityLayerId,
+        &StuckArrow,
+        &ProjectileOwner,
+        &ArrowEnchantments,
+    )>,
+    mut players: Query<(
+        Entity,
+        &mut Client,
+        &Position,
+        &EntityLayerId,
+        &GameMode,
+        &mut Inventory,
+    )>,
+    mut commands: Commands,
+    settings: Res<ArrowSettings>,
+    server: Res<Server>,
+) {
+    if settings.pickup == ArrowPickup::Nobody {
+        return;
+    }
+    for (arrow, arrow_pos, arrow_layer, stuck, owner, enchantments) in arrows.iter() {
+        if enchantments.infinity || server.current_tick() - stuck.since < settings.pickup_delay {
+            continue;
+        }
+        for (entity, mut client, pos, layer, mode, mut inv) in players.iter_mut() {
+            if layer.0 != arrow_layer.0
+                || *mode == GameMode::Spectator
+                || (settings.pickup == ArrowPickup::Owner && entity != owner.0)
             {
-                commands.entity(entity).insert(Despawned);
-                collisions.send(ProjectileCollisionEvent {
-                    arrow: entity,
-                    player: player_entity,
-                });
+                continue;
+            }
+            // The player's hitbox grown by a block sideways and half a block vertically
+            let offset = arrow_pos.0 - pos.0;
+            if offset.x.abs() > 1.3 || offset.z.abs() > 1.3 || offset.y < -0.5 || offset.y > 2.3 {
+                continue;
             }
+            if let Some(slot) = inv.first_slot_with_item(ItemKind::Arrow, 64) {
+                let count = inv.slot(slot).count;
+                inv.set_slot_amount(slot, count + 1);
+            } else if let Some(slot) = inv
+                .first_empty_slot_in(PlayerInventory::SLOTS_HOTBAR)
+                .or_else(|| inv.first_empty_slot_in(PlayerInventory::SLOTS_MAIN))
+            {
+                inv.set_slot(slot, ItemStack::new(ItemKind::Arrow, 1, None));
+            } else {
+                continue;
+            }
+            client.play_sound(
+                Sound::EntityItemPickup,
+                SoundCategory::Player,
+                pos.0,
+                0.2,
+                1.0,
+            );
+            commands.entity(arrow).insert(Despawned);
+            break;
         }
     }
 }
 
-fn cleanup_arrows(arrows: Query<(Entity, &Position), With<ArrowEntity>>, mut commands: Commands) {
-    for (entity, pos) in arrows.iter() {
-        if pos.0.y < -50.0 {
+fn cleanup_arrows(
+    arrows: Query<(Entity, &Position, Option<&StuckArrow>), With<ArrowEntity>>,
+    mut commands: Commands,
+    settings: Res<ArrowSettings>,
+    server: Res<Server>,
+) {
+    for (entity, pos, stuck) in arrows.iter() {
+        if pos.0.y < -50.0
+            || stuck
+                .is_some_and(|stuck| server.current_tick() - stuck.since >= settings.despawn_ticks)
+        {
             commands.entity(entity).insert(Despawned);
         }
     }
 }
+
+fn burn_players(
+    mut players: Query<(Entity, &mut Health, &mut Flags, &Burning)>,
+    mut deaths: EventWriter<DeathEvent>,
+    mut commands: Commands,
+    server: Res<Server>,
+) {
+    let tick = server.current_tick();
+    for (entity, mut health, mut flags, burning) in players.iter_mut() {
+        if tick >= burning.until {
+            flags.set_on_fire(false);
+            commands.entity(entity).remove::<Burning>();
+            continue;
+        }
+        if !flags.on_fire() {
+            flags.set_on_fire(true);
+        }
+        if (burning.until - tick) % 20 != 0 {
+            continue;
+        }
+        if health.0 <= 1.0 {
+            deaths.send(DeathEvent(entity, true));
+        } else {
+            health.0 -= 1.0;
+        }
+    }
+}
+
+/// Puts out players who died or moved to another layer, e.g. when their game ended.
+fn extinguish_players(
+    mut players: Query<(Entity, &mut Flags, Ref<EntityLayerId>), With<Burning>>,
+    mut deaths: EventReader<DeathEvent>,
+    mut commands: Commands,
+) {
+    let dead: Vec<Entity> = deaths.read().map(|DeathEvent(entity, _)| *entity).collect();
+    for (entity, mut flags, layer) in players.iter_mut() {
+        if layer.is_changed() || dead.contains(&entity) {
+            flags.set_on_fire(false);
+            commands.entity(entity).remove::<Burning>();
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use crate::config::ServerName;
+use crate::duels::{CombatState, HitRecord};
+use crate::network::{self, NetworkEvent, NetworkMessage, OnlinePlayer};
+use crate::scopes::has_scope;
+use std::sync::{LazyLock, Mutex};
+use std::time::SystemTime;
+use valence::{
+    command::{
+        AddCommand, CommandScopeRegistry, handler::CommandResultEvent, parsers::GreedyString,
+        scopes::CommandScopes,
+    },
+    command_macros::Command,
+    inventory::ClickSlotEvent,
+    nbt::compound,
+    prelude::*,
+};
+
+const STAFF_SCOPE: &str = "minibit.commands.staff.reports";
+
+// Shared between subservers so staff can see reports from every game
+static REPORTS: LazyLock<Mutex<Reports>> = LazyLock::new(|| Mutex::new(Reports::default()));
+
+#[derive(Default)]
+struct Reports {
+    next_id: i32,
+    open: Vec<Report>,
+}
+
+/// Inserted by subservers that have a `/spectate` command, so staff can click through to reported
+/// players on the same server.
+#[derive(Resource)]
+pub struct SpectateLinks;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReportReason {
+    Combat,
+    Movement,
+    Chat,
+    Teaming,
+    Other,
+}
+
+const REASONS: [ReportReason; 5] = [
+    ReportReason::Combat,
+    ReportReason::Movement,
+    ReportReason::Chat,
+    ReportReason::Teaming,
+    ReportReason::Other,
+];
+
+impl ReportReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportReason::Combat => "Combat hacks",
+            ReportReason::Movement => "Movement hacks",
+            ReportReason::Chat => "Chat abuse",
+            ReportReason::Teaming => "Teaming",
+            ReportReason::Other => "Other",
+        }
+    }
+
+    fn item(&self) -> ItemKind {
+        match self {
+            ReportReason::Combat => ItemKind::DiamondSword,
+            ReportReason::Movement => ItemKind::Feather,
+            ReportReason::Chat => ItemKind::Paper,
+            ReportReason::Teaming => ItemKind::Lead,
+            ReportReason::Other => ItemKind::Book,
+        }
+    }
+
+    fn parse(reason: &str) -> Option<Self> {
+        let reason = reason.to_lowercase();
+        REASONS.into_iter().find(|r| {
+            let name = r.name().to_lowercase();
+            name == reason || name.split(' ').next() == Some(reason.as_str())
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Evidence {
+    pub hits: Vec<HitRecord>,
+    pub position: DVec3,
+}
+
+impl Evidence {
+    /// Average number of ticks between consecutive hits.
+    pub fn attack_interval(&self) -> Option<f64> {
+        if self.hits.len() < 2 {
+            return None;
+        }
+        let ticks = self.hits.last().unwrap().tick - self.hits.first().unwrap().tick;
+        Some(ticks as f64 / (self.hits.len() - 1) as f64)
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "Position: {:.1}, {:.1}, {:.1}\nHits recorded: {}",
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            self.hits.len()
+        );
+        if let Some(interval) = self.attack_interval() {
+            summary += &format!("\nAttack interval: {:.1} ticks", interval);
+        }
+        if !self.hits.is_empty() {
+            let max = self.hits.iter().map(|hit| hit.reach).fold(0.0, f64::max);
+            let average =
+                self.hits.iter().map(|hit| hit.reach).sum::<f64>() / self.hits.len() as f64;
+            summary += &format!("\nReach: {:.2} avg, {:.2} max", average, max);
+        }
+        for hit in self.hits.iter().rev().take(5) {
+            summary += &format!(
+                "\n{} at tick {}: {:.2} blocks ({:.1}, {:.1}, {:.1})",
+                hit.victim,
+                hit.tick,
+                hit.reach,
+                hit.attacker_pos.x,
+                hit.attacker_pos.y,
+                hit.attacker_pos.z
+            );
+        }
+        summary
+    }
+}
+
+#[derive(Clone)]
+pub struct Report {
+    pub id: i32,
+    pub reporter: String,
+    pub target: String,
+    pub reason: ReportReason,
+    pub details: String,
+    pub server: String,
+    pub evidence: Option<Evidence>,
+    pub time: SystemTime,
+}
+
+#[derive(Component)]
+struct ReportMenu {
+    target: String,
+    inventory: Entity,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("report {target} {reason?}")]
+#[scopes("minibit.commands.all.report")]
+struct ReportCommand {
+    target: String,
+    reason: Option<GreedyString>,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("reports")]
+#[scopes("minibit.commands.staff.reports")]
+struct ReportsCommand {}
+
+#[derive(Command, Debug, Clone)]
+#[paths("closereport {id}")]
+#[scopes("minibit.commands.staff.reports")]
+struct CloseReportCommand {
+    id: i32,
+}
+
+pub struct ReportPlugin;
+
+impl Plugin for ReportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command::<ReportCommand>()
+            .add_command::<ReportsCommand>()
+            .add_command::<CloseReportCommand>()
+            .add_systems(
+                Update,
+                (
+                    handle_report_command,
+                    handle_reports_command,
+                    handle_close_command,
+                    close_menus,
+                    deliver_alerts,
+                ),
+            )
+            .add_systems(EventLoopUpdate, handle_menu_click);
+    }
+}
+
+fn handle_report_command(
+    mut events: EventReader<CommandResultEvent<ReportCommand>>,
+    mut clients: Query<(&mut Client, &Username)>,
+    suspects: Query<(&Username, &Position, Option<&CombatState>)>,
+    mut commands: Commands,
+) {
+    for event in events.read() {
+        let Ok((mut client, username)) = clients.get_mut(event.executor) else {
+            continue;
+        };
+        let Some(target) = network::find_player(&event.result.target) else {
+            client.send_chat_message(
+                (String::from("Player ") + &event.result.target + " is not online!")
+                    .color(Color::RED),
+            );
+            continue;
+        };
+        if target.username.eq_ignore_ascii_case(&username.0) {
+            client.send_chat_message("You can't report yourself!".color(Color::RED));
+            continue;
+        }
+
+        let Some(reason) = &event.result.reason else {
+            let inventory = commands.spawn(report_menu(&target.username)).id();
+            commands.entity(event.executor).insert((
+                ReportMenu {
+                    target: target.username,
+                    inventory,
+                },
+                OpenInventory::new(inventory),
+            ));
+            continue;
+        };
+        let (reason, details) = match ReportReason::parse(&reason.0) {
+            Some(reason) => (reason, String::new()),
+            None => (ReportReason::Other, reason.0.clone()),
+        };
+        file_report(&mut client, &username.0, target, reason, details, &suspects);
+    }
+}
+
+fn report_menu(target: &str) -> Inventory {
+    let mut inventory =
+        Inventory::with_title(InventoryKind::Generic9x1, format!("Report {}", target));
+    inventory.readonly = true;
+    for (i, reason) in REASONS.iter().enumerate() {
+        inventory.set_slot(
+            (i * 2) as u16,
+            ItemStack::new(
+                reason.item(),
+                1,
+                Some(compound! {
+                    "display" => compound! {
+                        "Name" => format!("{{\"text\":\"{}\",\"italic\":false}}", reason.name())
+                    },
+                }),
+            ),
+        );
+    }
+    inventory
+}
+
+fn handle_menu_click(
+    mut clients: Query<(&mut Client, &Username, &ReportMenu)>,
+    suspects: Query<(&Username, &Position, Option<&CombatState>)>,
+    mut click_slot: EventReader<ClickSlotEvent>,
+    mut commands: Commands,
+) {
+    for event in click_slot.read() {
+        let Ok((mut client, username, menu)) = clients.get_mut(event.client) else {
+            continue;
+        };
+        if event.window_id == 0 || event.slot_id < 0 || event.slot_id % 2 != 0 {
+            continue;
+        }
+        let Some(reason) = REASONS.get(event.slot_id as usize / 2) else {
+            continue;
+        };
+
+        commands.entity(menu.inventory).insert(Despawned);
+        commands
+            .entity(event.client)
+            .remove::<(ReportMenu, OpenInventory)>();
+
+        match network::find_player(&menu.target) {
+            Some(target) => file_report(
+                &mut client,
+                &username.0,
+                target,
+                *reason,
+                String::new(),
+                &suspects,
+            ),
+            None => client.send_chat_message(
+                (menu.target.clone() + " is no longer online!").color(Color::RED),
+            ),
+        }
+    }
+}
+
+fn close_menus(
+    clients: Query<(Entity, &ReportMenu), Without<OpenInventory>>,
+    mut commands: Commands,
+) {
+    for (entity, menu) in clients.iter() {
+        commands.entity(menu.inventory).insert(Despawned);
+        commands.entity(entity).remove::<ReportMenu>();
+    }
+}
+
+fn file_report(
+    client: &mut Client,
+    reporter: &str,
+    target: OnlinePlayer,
+    reason: ReportReason,
+    details: String,
+    suspects: &Query<(&Username, &Position, Option<&CombatState>)>,
+) {
+    // Combat data is only available if the suspect is on this subserver
+    let evidence = suspects
+        .iter()
+        .find(|(username, _, _)| username.0 == target.username)
+        .map(|(_, pos, state)| Evidence {
+            hits: state.map_or(Vec::new(), |state| state.hits.iter().cloned().collect()),
+            position: pos.0,
+        });
+
+    let id = {
+        let mut reports = REPORTS.lock().unwrap();
+        if reports
+            .open
+            .iter()
+            .any(|report| report.reporter == reporter && report.target == target.username)
+        {
+            client.send_chat_message(
+                (String::from("You already have an open report on ") + &target.username + "!")
+                    .color(Color::RED),
+            );
+            return;
+        }
+        reports.next_id += 1;
+        let id = reports.next_id;
+        reports.open.push(Report {
+            id,
+            reporter: reporter.to_string(),
+            target: target.username.clone(),
+            reason,
+            details,
+            server: target.server.clone(),
+            evidence,
+            time: SystemTime::now(),
+        });
+        id
+    };
+
+    client.send_chat_message(
+        (String::from("Thanks! Your report on ") + &target.username + " has been sent to staff.")
+            .color(Color::GREEN),
+    );
+    network::broadcast(NetworkMessage::Report {
+        id,
+        reporter: reporter.to_string(),
+        target: target.username,
+        reason: reason.name().to_string(),
+        server: target.server,
+    });
+}
+
+fn deliver_alerts(
+    mut clients: Query<(&mut Client, &CommandScopes)>,
+    mut events: EventReader<NetworkEvent>,
+    registry: Res<CommandScopeRegistry>,
+    server_name: Res<ServerName>,
+    links: Option<Res<SpectateLinks>>,
+) {
+    for NetworkEvent(message) in events.read() {
+        let NetworkMessage::Report {
+            id,
+            reporter,
+            target,
+            reason,
+            server,
+        } = message
+        else {
+            continue;
+        };
+        for (mut client, scopes) in clients.iter_mut() {
+            if !has_scope(&registry, scopes, STAFF_SCOPE) {
+                continue;
+            }
+            client.send_chat_message(
+                Text::from("[REPORT] ").color(Color::RED)
+                    + Text::from(format!(
+                        "#{} {} reported {} for {} on {}. ",
+                        id, reporter, target, reason, server
+                    ))
+                    .color(Color::GRAY)
+                    + spectate_button(target, *server == server_name.0 && links.is_some()),
+            );
+        }
+    }
+}
+
+fn handle_reports_command(
+    mut events: EventReader<CommandResultEvent<ReportsCommand>>,
+    mut clients: Query<&mut Client>,
+    server_name: Res<ServerName>,
+    links: Option<Res<SpectateLinks>>,
+) {
+    for event in events.read() {
+        let Ok(mut client) = clients.get_mut(event.executor) else {
+            continue;
+        };
+        let reports = REPORTS.lock().unwrap();
+        if reports.open.is_empty() {
+            client.send_chat_message("There are no open reports.".color(Color::GREEN));
+            continue;
+        }
+
+        client.send_chat_message(
+            format!("Open reports ({}):", reports.open.len()).color(Color::GOLD),
+        );
+        for report in reports.open.iter() {
+            let minutes = report.time.elapsed().unwrap_or_default().as_secs() / 60;
+            let mut reason = report.reason.name().to_string();
+            if !report.details.is_empty() {
+                reason += &(String::from(": ") + &report.details);
+            }
+            let evidence = match &report.evidence {
+                Some(evidence) => evidence.summary(),
+                None => "No combat data was captured.".to_string(),
+            };
+
+            client.send_chat_message(
+                Text::from(format!("#{} ", report.id)).color(Color::GOLD)
+                    + Text::from(report.target.clone()).color(Color::WHITE)
+                    + Text::from(format!(
+                        " ({}) by {}, {}m ago on {} ",
+                        reason, report.reporter, minutes, report.server
+                    ))
+                    .color(Color::GRAY)
+                    + Text::from("[Evidence] ")
+                        .color(Color::YELLOW)
+                        .on_hover_show_text(evidence)
+                    + spectate_button(
+                        &report.target,
+                        report.server == server_name.0 && links.is_some(),
+                    )
+                    + Text::from("[Close]")
+                        .color(Color::RED)
+                        .on_click_run_command(format!("/closereport {}", report.id)),
+            );
+        }
+    }
+}
+
+fn handle_close_command(
+    mut events: EventReader<CommandResultEvent<CloseReportCommand>>,
+    mut clients: Query<&mut Client>,
+) {
+    for event in events.read() {
+        let Ok(mut client) = clients.get_mut(event.executor) else {
+            continue;
+        };
+        let mut reports = REPORTS.lock().unwrap();
+        let count = reports.open.len();
+        reports.open.retain(|report| report.id != event.result.id);
+        client.send_chat_message(if reports.open.len() < count {
+            format!("Closed report #{}.", event.result.id).color(Color::GREEN)
+        } else {
+            format!("There is no open report #{}!", event.result.id).color(Color::RED)
+        });
+    }
+}
+
+pub(crate) fn spectate_button(target: &str, enabled: bool) -> Text {
+    if !enabled {
+        return Text::from("");
+    }
+    Text::from("[Spectate] ")
+        .color(Color::AQUA)
+        .on_click_run_command(format!("/spectate {}", target))
+}
@@ -14,6 +14,9 @@ impl Plugin for ScopePlugin {
 
 fn setup(mut command_scopes: ResMut<CommandScopeRegistry>) {
     command_scopes.link("minibit.all", "minibit.commands.all");
+    command_scopes.link("minibit.staff", "minibit.anticheat.alerts");
+    command_scopes.link("minibit.staff", "minibit.chat.bypass");
+    command_scopes.link("minibit.staff", "minibit.commands.staff");
 }
 
 fn add_default_scope(mut clients: Query<&mut CommandScopes, Added<Client>>) {
@@ -21,3 +24,7 @@ fn add_default_scope(mut clients: Query<&mut CommandScopes, Added<Client>>) {
         scopes.add("minibit.all");
     }
 }
+
+pub fn has_scope(registry: &CommandScopeRegistry, scopes: &CommandScopes, scope: &str) -> bool {
+    scopes.0.iter().any(|s| registry.grants(s, scope))
+}
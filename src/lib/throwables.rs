@@ -0,0 +1,504 @@
+#![allow(clippy::type_complexity)]
+
+use crate::anticheat::movement::MovementState;
+use crate::combat::{CombatQuery, KnockbackProfile, damage_player};
+use crate::death::DeathEvent;
+use crate::projectiles::{
+    ProjectileBlockHitEvent, ProjectileCollisionEvent, ProjectileHit, ProjectileLayer,
+    ProjectileOwner, launch_direction, launch_origin, sweep_projectile,
+};
+use valence::{
+    entity::{
+        EntityId, ObjectData, Velocity, egg::EggEntityBundle, ender_pearl::EnderPearlEntityBundle,
+        entity::Flags, fishing_bobber::FishingBobberEntityBundle, living::Absorption,
+        living::Health, snowball::SnowballEntityBundle,
+    },
+    interact_item::InteractItemEvent,
+    inventory::{HeldItem, player_inventory::PlayerInventory},
+    prelude::*,
+    protocol::{Sound, sound::SoundCategory},
+};
+
+/// Half the size of a thrown projectile's hitbox, in blocks.
+const THROWABLE_SIZE: f32 = 0.125;
+/// Ticks before a projectile can hit the player who threw it.
+const OWNER_IMMUNITY_TICKS: i64 = 5;
+/// Fall damage taken when landing an ender pearl.
+const PEARL_DAMAGE: f32 = 5.0;
+/// Bobbers further than this from their owner are reeled in automatically.
+const MAX_LINE_LENGTH: f64 = 32.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ThrowableEntity {
+    Snowball,
+    Egg,
+    EnderPearl,
+    FishingBobber,
+}
+
+/// What a projectile does when it hits something.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OnHit {
+    /// Knocks players back without damage and breaks on blocks.
+    Knockback,
+    /// Teleports the thrower to where it landed, dealing fall damage.
+    Teleport,
+    /// Knocks players back and hooks onto them or sticks in blocks until the rod is used again,
+    /// which pulls the hooked player towards the thrower.
+    Hook,
+}
+
+/// An item that launches a projectile when used.
+#[derive(Clone, Copy)]
+pub struct Throwable {
+    pub item: ItemKind,
+    pub entity: ThrowableEntity,
+    /// Launch speed in blocks per second.
+    pub speed: f32,
+    /// Subtracted from the vertical velocity every tick, in blocks per second.
+    pub gravity: f32,
+    /// Velocity is multiplied by this every tick.
+    pub drag: f32,
+    pub on_hit: OnHit,
+    /// Whether using the item uses it up.
+    pub consumed: bool,
+}
+
+impl Throwable {
+    pub const SNOWBALL: Self = Self {
+        item: ItemKind::Snowball,
+        entity: ThrowableEntity::Snowball,
+        speed: 30.0,
+        gravity: 0.6,
+        drag: 0.99,
+        on_hit: OnHit::Knockback,
+        consumed: true,
+    };
+    pub const EGG: Self = Self {
+        item: ItemKind::Egg,
+        entity: ThrowableEntity::Egg,
+        ..Self::SNOWBALL
+    };
+    pub const ENDER_PEARL: Self = Self {
+        item: ItemKind::EnderPearl,
+        entity: ThrowableEntity::EnderPearl,
+        on_hit: OnHit::Teleport,
+        ..Self::SNOWBALL
+    };
+    pub const FISHING_ROD: Self = Self {
+        item: ItemKind::FishingRod,
+        entity: ThrowableEntity::FishingBobber,
+        speed: 30.0,
+        gravity: 0.8,
+        drag: 0.92,
+        on_hit: OnHit::Hook,
+        consumed: false,
+    };
+}
+
+#[derive(Resource)]
+struct Throwables(Vec<Throwable>);
+
+/// A projectile launched from a `Throwable`, since the given tick.
+#[derive(Component)]
+pub struct Thrown {
+    pub throwable: Throwable,
+    pub since: i64,
+}
+
+/// Set on a bobber that hooked a player.
+#[derive(Component)]
+pub struct Hooked(pub Entity);
+
+/// Set on a bobber stuck in a block.
+#[derive(Component)]
+pub struct Grounded;
+
+/// The bobber a player currently has out.
+#[derive(Component)]
+pub struct FishingHook(pub Entity);
+
+/// Lets players use the given items to throw projectiles. Needs `CombatPlugin` for knockback.
+pub struct ThrowablePlugin {
+    pub throwables: Vec<Throwable>,
+}
+
+impl Default for ThrowablePlugin {
+    fn default() -> Self {
+        Self {
+            throwables: vec![
+                Throwable::SNOWBALL,
+                Throwable::EGG,
+                Throwable::ENDER_PEARL,
+                Throwable::FISHING_ROD,
+            ],
+        }
+    }
+}
+
+impl Plugin for ThrowablePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Throwables(self.throwables.clone()))
+            .add_event::<ProjectileCollisionEvent>()
+            .add_event::<ProjectileBlockHitEvent>()
+            .add_event::<DeathEvent>()
+            .add_systems(
+                Update,
+                (
+                    use_throwables,
+                    apply_throwable_physics,
+                    knock_back_players.after(apply_throwable_physics),
+                    land_pearls.after(apply_throwable_physics),
+                    update_hooks.after(knock_back_players),
+                ),
+            );
+    }
+}
+
+fn use_throwables(
+    mut players: Query<(
+        &mut Inventory,
+        &HeldItem,
+        &GameMode,
+        &Position,
+        &Look,
+        &HeadYaw,
+        &Flags,
+        &EntityId,
+        &EntityLayerId,
+        &VisibleChunkLayer,
+        Option<&FishingHook>,
+    )>,
+    mut clients: Query<&mut Client>,
+    hooks: Query<Option<&Hooked>>,
+    mut movement: Query<&mut MovementState>,
+    positions: Query<&Position>,
+    mut events: EventReader<InteractItemEvent>,
+    mut commands: Commands,
+    throwables: Res<Throwables>,
+    server: Res<Server>,
+) {
+    for event in events.read() {
+        let Ok((
+            mut inv,
+            held_item,
+            mode,
+            pos,
+            look,
+            yaw,
+            flags,
+            id,
+            layer,
+            chunk_layer,
+            fishing_hook,
+        )) = players.get_mut(event.client)
+        else {
+            continue;
+        };
+        let slot = match event.hand {
+            Hand::Main => held_item.slot(),
+            Hand::Off => PlayerInventory::SLOT_OFFHAND,
+        };
+        let stack = inv.slot(slot);
+        let Some(throwable) = throwables.0.iter().find(|t| t.item == stack.item).copied() else {
+            continue;
+        };
+
+        // Using a rod with a bobber out reels it in instead of casting a new one
+        if throwable.on_hit == OnHit::Hook
+            && let Some(FishingHook(bobber)) = fishing_hook
+        {
+            if let Ok(Some(Hooked(victim))) = hooks.get(*bobber)
+                && let Ok(victim_pos) = positions.get(*victim)
+            {
+                let offset = pos.0 - victim_pos.0;
+                let pull = (offset * 0.1 + DVec3::Y * offset.length().sqrt() * 0.08) * 20.0;
+                if let Ok(mut client) = clients.get_mut(*victim) {
+                    client.set_velocity(pull.as_vec3());
+                }
+                if let Ok(mut state) = movement.get_mut(*victim) {
+                    state.add_velocity(pull.as_vec3());
+                }
+            }
+            commands.entity(*bobber).insert(Despawned);
+            commands.entity(event.client).remove::<FishingHook>();
+            if let Ok(mut client) = clients.get_mut(event.client) {
+                client.play_sound(
+                    Sound::EntityFishingBobberRetrieve,
+                    SoundCategory::Neutral,
+                    pos.0,
+                    1.0,
+                    0.4 / (fastrand::f32() * 0.4 + 0.8),
+                );
+            }
+            continue;
+        }
+
+        if throwable.consumed && *mode != GameMode::Creative {
+            let count = stack.count;
+            inv.set_slot_amount(slot, count - 1);
+        }
+
+        let position = Position(launch_origin(pos.0, yaw.0, flags));
+        let velocity = Velocity(launch_direction(yaw.0, look.pitch) * throwable.speed);
+        let layer = *layer;
+        let mut entity = match throwable.entity {
+            ThrowableEntity::Snowball => commands.spawn(SnowballEntityBundle {
+                position,
+                velocity,
+                layer,
+                ..Default::default()
+            }),
+            ThrowableEntity::Egg => commands.spawn(EggEntityBundle {
+                position,
+                velocity,
+                layer,
+                ..Default::default()
+            }),
+            ThrowableEntity::EnderPearl => commands.spawn(EnderPearlEntityBundle {
+                position,
+                velocity,
+                layer,
+                ..Default::default()
+            }),
+            // The client draws the line to the entity in the object data
+            ThrowableEntity::FishingBobber => commands.spawn(FishingBobberEntityBundle {
+                position,
+                velocity,
+                layer,
+                object_data: ObjectData(id.get()),
+                ..Default::default()
+            }),
+        };
+        let projectile = entity
+            .insert((
+                ProjectileOwner(event.client),
+                ProjectileLayer(chunk_layer.0),
+                Thrown {
+                    throwable,
+                    since: server.current_tick(),
+                },
+            ))
+            .id();
+        if throwable.on_hit == OnHit::Hook {
+            commands
+                .entity(event.client)
+                .insert(FishingHook(projectile));
+        }
+
+        let sound = match throwable.entity {
+            ThrowableEntity::Snowball => Sound::EntitySnowballThrow,
+            ThrowableEntity::Egg => Sound::EntityEggThrow,
+            ThrowableEntity::EnderPearl => Sound::EntityEnderPearlThrow,
+            ThrowableEntity::FishingBobber => Sound::EntityFishingBobberThrow,
+        };
+        for mut client in clients.iter_mut() {
+            client.play_sound(
+                sound,
+                SoundCategory::Neutral,
+                pos.0,
+                0.5,
+                0.4 / (fastrand::f32() * 0.4 + 0.8),
+            );
+        }
+    }
+}
+
+fn apply_throwable_physics(
+    mut projectiles: Query<
+        (
+            Entity,
+            &mut Position,
+            &mut Velocity,
+            &ProjectileLayer,
+            &ProjectileOwner,
+            &Thrown,
+            &EntityLayerId,
+        ),
+        (Without<Grounded>, Without<Hooked>, Without<Client>),
+    >,
+    players: Query<(Entity, &Position, &EntityLayerId, &GameMode), With<Client>>,
+    layers: Query<&ChunkLayer>,
+    mut collisions: EventWriter<ProjectileCollisionEvent>,
+    mut block_hits: EventWriter<ProjectileBlockHitEvent>,
+    mut commands: Commands,
+    server: Res<Server>,
+) {
+    for (entity, mut pos, mut vel, layer, owner, thrown, projectile_layer) in projectiles.iter_mut()
+    {
+        let step = DVec3::from(vel.0) / 20.0;
+        let targets = players
+            .iter()
+            .filter(|(player, _, player_layer, mode)| {
+                player_layer.0 == projectile_layer.0
+                    && **mode != GameMode::Spectator
+                    && (*player != owner.0
+                        || server.current_tick() - thrown.since >= OWNER_IMMUNITY_TICKS)
+            })
+            .map(|(player, player_pos, _, _)| (player, player_pos.0));
+
+        match sweep_projectile(
+            layers.get(layer.0).ok(),
+            pos.0,
+            step,
+            THROWABLE_SIZE,
+            targets,
+        ) {
+            Some((ProjectileHit::Player(player), t)) => {
+                pos.0 += step * t;
+                collisions.send(ProjectileCollisionEvent {
+                    projectile: entity,
+                    player,
+                });
+                if thrown.throwable.on_hit == OnHit::Hook {
+                    vel.0 = Vec3::ZERO;
+                    commands.entity(entity).insert(Hooked(player));
+                } else {
+                    commands.entity(entity).insert(Despawned);
+                }
+            }
+            Some((ProjectileHit::Block(block), t)) => {
+                pos.0 += step * t - step.normalize_or_zero() * 0.05;
+                block_hits.send(ProjectileBlockHitEvent {
+                    projectile: entity,
+                    block,
+                });
+                if thrown.throwable.on_hit == OnHit::Hook {
+                    vel.0 = Vec3::ZERO;
+                    commands.entity(entity).insert(Grounded);
+                } else {
+                    commands.entity(entity).insert(Despawned);
+                }
+            }
+            None => {
+                pos.0 += step;
+                vel.0 *= thrown.throwable.drag;
+                vel.0.y -= thrown.throwable.gravity;
+                if pos.0.y < -50.0 {
+                    commands.entity(entity).insert(Despawned);
+                }
+            }
+        }
+    }
+}
+
+/// Every throwable hit deals no damage but knocks the victim back like an unsprinted melee hit,
+/// in the direction the projectile was flying. It shares the melee hit cooldown.
+fn knock_back_players(
+    mut players: Query<CombatQuery>,
+    projectiles: Query<(&Velocity, &ProjectileOwner), With<Thrown>>,
+    mut collisions: EventReader<ProjectileCollisionEvent>,
+    knockback: Option<Res<KnockbackProfile>>,
+    server: Res<Server>,
+) {
+    let knockback = knockback.as_deref().copied().unwrap_or_default();
+    for event in collisions.read() {
+        let Ok((vel, owner)) = projectiles.get(event.projectile) else {
+            continue;
+        };
+        let Ok([mut attacker, mut victim]) = players.get_many_mut([owner.0, event.player]) else {
+            continue;
+        };
+        if attacker.gamestate.game_id != victim.gamestate.game_id
+            || attacker.gamestate.team == victim.gamestate.team
+            || server.current_tick() - victim.state.last_attacked_tick < knockback.hit_cooldown
+        {
+            continue;
+        }
+        let dir = vel.0.with_y(0.0).normalize_or_zero() * knockback.horizontal;
+        victim.state.last_attacked_tick = server.current_tick();
+        damage_player(
+            &mut attacker,
+            &mut victim,
+            0.0,
+            Vec3::new(dir.x, knockback.vertical, dir.z),
+            &knockback,
+        );
+    }
+}
+
+fn land_pearls(
+    mut players: Query<(&mut Position, &mut Health, &mut Absorption), With<Client>>,
+    projectiles: Query<(&Position, &ProjectileOwner, &Thrown, &EntityLayerId), Without<Client>>,
+    player_layers: Query<&EntityLayerId, With<Client>>,
+    mut collisions: EventReader<ProjectileCollisionEvent>,
+    mut block_hits: EventReader<ProjectileBlockHitEvent>,
+    mut deaths: EventWriter<DeathEvent>,
+) {
+    let landed = collisions
+        .read()
+        .map(|event| event.projectile)
+        .chain(block_hits.read().map(|event| event.projectile));
+    for projectile in landed {
+        let Ok((pearl_pos, owner, thrown, layer)) = projectiles.get(projectile) else {
+            continue;
+        };
+        // Pearls still in flight when their thrower leaves the game do nothing
+        if thrown.throwable.on_hit != OnHit::Teleport
+            || !player_layers
+                .get(owner.0)
+                .is_ok_and(|player_layer| player_layer.0 == layer.0)
+        {
+            continue;
+        }
+        let Ok((mut pos, mut health, mut absorption)) = players.get_mut(owner.0) else {
+            continue;
+        };
+        pos.set(pearl_pos.0);
+
+        let absorbed = PEARL_DAMAGE.min(absorption.0);
+        absorption.0 -= absorbed;
+        let damage = PEARL_DAMAGE - absorbed;
+        if health.0 <= damage {
+            deaths.send(DeathEvent(owner.0, true));
+        } else {
+            health.0 -= damage;
+        }
+    }
+}
+
+/// Keeps hooked bobbers on their victim and reels in bobbers whose owner switched items, left or
+/// got too far away.
+fn update_hooks(
+    mut bobbers: Query<(
+        Entity,
+        &mut Position,
+        &ProjectileOwner,
+        &EntityLayerId,
+        &Thrown,
+        Option<&Hooked>,
+    )>,
+    players: Query<(&Position, &EntityLayerId, &Inventory, &HeldItem), Without<Thrown>>,
+    mut commands: Commands,
+) {
+    for (bobber, mut pos, owner, layer, thrown, hooked) in bobbers.iter_mut() {
+        if thrown.throwable.on_hit != OnHit::Hook {
+            continue;
+        }
+        let Ok((owner_pos, owner_layer, inv, held_item)) = players.get(owner.0) else {
+            commands.entity(bobber).insert(Despawned);
+            continue;
+        };
+        if (inv.slot(held_item.slot()).item != thrown.throwable.item
+            && inv.slot(PlayerInventory::SLOT_OFFHAND).item != thrown.throwable.item)
+            || owner_layer.0 != layer.0
+            || owner_pos.0.distance(pos.0) > MAX_LINE_LENGTH
+        {
+            reel_in(&mut commands, bobber, owner.0);
+            continue;
+        }
+        if let Some(Hooked(victim)) = hooked {
+            match players.get(*victim) {
+                Ok((victim_pos, victim_layer, _, _)) if victim_layer.0 == layer.0 => {
+                    pos.set(victim_pos.0 + DVec3::new(0.0, 1.44, 0.0));
+                }
+                _ => reel_in(&mut commands, bobber, owner.0),
+            }
+        }
+    }
+}
+
+fn reel_in(commands: &mut Commands, bobber: Entity, owner: Entity) {
+    commands.entity(bobber).insert(Despawned);
+    commands.entity(owner).remove::<FishingHook>();
+}